@@ -1,7 +1,5 @@
 use anyhow::Result;
-use chrono;
 use log::{debug, error, info};
-use market_monitor;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -6,6 +6,9 @@ use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 use url::Url;
 
+/// Maximum number of entities The Graph returns for a single collection query
+pub const MAX_PAGE_SIZE: usize = 1000;
+
 /// A client for interacting with The Graph API
 #[derive(Debug, Clone)]
 pub struct GraphClient {
@@ -167,4 +170,102 @@ impl GraphClient {
             }
         }
     }
+
+    /// Execute a collection query repeatedly, following an `id_gt` cursor, until the
+    /// collection is exhausted or `limit` entities have been collected.
+    ///
+    /// The query must declare `$first: Int!` and `$lastId` variables, order `collection`
+    /// by `id` ascending and filter it with `id_gt: $lastId`. The cursor starts at `0x`,
+    /// which sorts before every hex-encoded id.
+    pub async fn query_paginated<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        collection: &str,
+        variables: Value,
+        limit: Option<usize>,
+    ) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut last_id = String::from("0x");
+
+        loop {
+            let page_size = match limit {
+                Some(limit) => (limit - items.len()).min(MAX_PAGE_SIZE),
+                None => MAX_PAGE_SIZE,
+            };
+            if page_size == 0 {
+                break;
+            }
+
+            let mut page_variables = variables.clone();
+            page_variables["first"] = Value::from(page_size);
+            page_variables["lastId"] = Value::from(last_id.clone());
+
+            let data: Value = self.query_raw(query, page_variables).await?;
+            let page = match data.get(collection) {
+                Some(Value::Array(page)) => page.clone(),
+                _ => return Err(anyhow!("Missing '{}' collection in response", collection)),
+            };
+
+            let page_len = page.len();
+            debug!("Fetched page of {} '{}' entities", page_len, collection);
+
+            for entity in page {
+                last_id = entity
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| anyhow!("Entity in '{}' has no id", collection))?
+                    .to_string();
+                let parsed = serde_json::from_value(entity)
+                    .map_err(|e| anyhow!("Failed to parse '{}' entity: {}", collection, e))?;
+                items.push(parsed);
+            }
+
+            if page_len < page_size {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Fetch the newest `limit` entities of a collection, newest first when a limit is set.
+    ///
+    /// The query follows the [`GraphClient::query_paginated`] contract but takes its order
+    /// from `$orderBy` and `$orderDirection` variables. A limit that fits in one page is
+    /// served by a single query ordered by `order_by` descending. Otherwise every entity is
+    /// fetched through the id cursor, and the caller sorts and truncates.
+    pub async fn query_newest<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        collection: &str,
+        order_by: &str,
+        variables: Value,
+        limit: Option<usize>,
+    ) -> Result<Vec<T>> {
+        let mut variables = variables;
+        let Some(limit) = limit.filter(|limit| *limit <= MAX_PAGE_SIZE) else {
+            variables["orderBy"] = Value::from("id");
+            variables["orderDirection"] = Value::from("asc");
+            return self
+                .query_paginated(query, collection, variables, None)
+                .await;
+        };
+
+        variables["orderBy"] = Value::from(order_by);
+        variables["orderDirection"] = Value::from("desc");
+        variables["first"] = Value::from(limit);
+        variables["lastId"] = Value::from("0x");
+
+        let data: Value = self.query_raw(query, variables).await?;
+        let page = match data.get(collection) {
+            Some(Value::Array(page)) => page.clone(),
+            _ => return Err(anyhow!("Missing '{}' collection in response", collection)),
+        };
+        page.into_iter()
+            .map(|entity| {
+                serde_json::from_value(entity)
+                    .map_err(|e| anyhow!("Failed to parse '{}' entity: {}", collection, e))
+            })
+            .collect()
+    }
 }
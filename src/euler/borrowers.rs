@@ -0,0 +1,148 @@
+//! Enumeration of Euler borrowers from the EVC active-account tracking.
+//!
+//! `TrackingActiveAccount` lists, per main address, every sub-account and vault pair it
+//! deposits in or borrows from, encoded as the account address followed by the vault address.
+//! The same encoding is the id of the `TrackingVaultBalance` holding the pair's debt, which is
+//! also filterable by vault, so the borrowers of a vault are read from the balances directly.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::VaultBalance;
+use crate::client::GraphClient;
+use crate::numeric::to_f64;
+
+/// Length of a hex encoded address without the `0x` prefix
+const ADDRESS_HEX_LEN: usize = 40;
+
+/// Sub-account and vault pairs a main address is active in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveAccount {
+    pub id: String,
+    #[serde(rename = "mainAddress")]
+    pub main_address: String,
+    /// Account address followed by vault address, for each deposit
+    pub deposits: Vec<String>,
+    /// Account address followed by vault address, for each borrow
+    pub borrows: Vec<String>,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "blockTimestamp")]
+    pub block_timestamp: String,
+}
+
+impl ActiveAccount {
+    /// (account, vault) pairs the main address borrows in
+    pub fn borrow_pairs(&self) -> Vec<(String, String)> {
+        self.borrows
+            .iter()
+            .filter_map(|pair| split_account_vault(pair))
+            .collect()
+    }
+
+    /// (account, vault) pairs the main address deposits in
+    pub fn deposit_pairs(&self) -> Vec<(String, String)> {
+        self.deposits
+            .iter()
+            .filter_map(|pair| split_account_vault(pair))
+            .collect()
+    }
+}
+
+/// Split an account and vault pair into its two addresses
+pub fn split_account_vault(pair: &str) -> Option<(String, String)> {
+    let hex = pair.trim_start_matches("0x").to_lowercase();
+    if hex.len() != 2 * ADDRESS_HEX_LEN {
+        return None;
+    }
+    let (account, vault) = hex.split_at(ADDRESS_HEX_LEN);
+    Some((format!("0x{}", account), format!("0x{}", vault)))
+}
+
+/// Fetch every main address with at least one active borrow
+pub async fn fetch_borrower_universe(client: &GraphClient) -> Result<Vec<ActiveAccount>> {
+    let query = r#"
+    query EulerActiveBorrowers($first: Int!, $lastId: Bytes!) {
+        trackingActiveAccounts(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, borrows_not: [] }
+        ) {
+            id
+            mainAddress
+            deposits
+            borrows
+            blockNumber
+            blockTimestamp
+        }
+    }
+    "#;
+
+    client
+        .query_paginated(query, "trackingActiveAccounts", json!({}), None)
+        .await
+}
+
+/// Fetch the active borrowers of a vault with their debt, largest debt first
+pub async fn fetch_vault_borrowers(client: &GraphClient, vault: &str) -> Result<Vec<VaultBalance>> {
+    let query = r#"
+    query EulerBorrowerBalances($first: Int!, $lastId: Bytes!, $vault: Bytes!) {
+        trackingVaultBalances(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, vault: $vault, debt_gt: "0" }
+        ) {
+            id
+            vault
+            account
+            mainAddress
+            balance
+            debt
+            isControllerEnabled
+        }
+    }
+    "#;
+
+    let mut borrowers: Vec<VaultBalance> = client
+        .query_paginated(
+            query,
+            "trackingVaultBalances",
+            json!({ "vault": vault.to_lowercase() }),
+            None,
+        )
+        .await?;
+    borrowers.sort_by(|a, b| to_f64(&b.debt).total_cmp(&to_f64(&a.debt)));
+    Ok(borrowers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_account_vault_pairs() {
+        let account = |n: u8| format!("0x{}{:02x}", "00".repeat(19), n);
+        let vault = format!("0x{}", "ab".repeat(20));
+        let other = format!("0x{}", "cd".repeat(20));
+        let pair = |a: &str, v: &str| format!("{}{}", a, v.trim_start_matches("0x"));
+
+        let active = ActiveAccount {
+            id: account(1),
+            main_address: account(1),
+            deposits: vec![pair(&account(1), &other)],
+            borrows: vec![pair(&account(1), &vault), pair(&account(2), &other)],
+            block_number: "1".to_string(),
+            block_timestamp: "1".to_string(),
+        };
+
+        assert_eq!(active.deposit_pairs(), vec![(account(1), other.clone())]);
+        assert_eq!(
+            active.borrow_pairs(),
+            vec![(account(1), vault), (account(2), other)]
+        );
+        assert!(split_account_vault("0x1234").is_none());
+    }
+}
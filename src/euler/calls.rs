@@ -0,0 +1,407 @@
+//! EVC calls recorded by `CallWithContext`, decoded and grouped into batches.
+//!
+//! The subgraph writes one `CallWithContext` per transaction and vault, with the 4-byte
+//! selector of the EVault function called. Grouping the calls of a transaction reconstructs
+//! what an EVC batch did, e.g. borrowing in one vault and depositing in another to lever up.
+//! Calls within a transaction are not ordered, so patterns are classified from the set of
+//! operations only.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::client::{GraphClient, MAX_PAGE_SIZE};
+
+/// EVault functions by 4-byte selector, the first 4 bytes of the keccak-256 hash of the
+/// function signature
+pub const EVAULT_SELECTORS: [(&str, &str); 18] = [
+    ("0x6e553f65", "deposit"),
+    ("0x94bf804d", "mint"),
+    ("0xb460af94", "withdraw"),
+    ("0xba087652", "redeem"),
+    ("0x8d56c639", "skim"),
+    ("0x4b3fd148", "borrow"),
+    ("0xacb70815", "repay"),
+    ("0xa9c8eb7e", "repayWithShares"),
+    ("0xaebde56b", "pullDebt"),
+    ("0x5296a431", "flashLoan"),
+    ("0xa55526db", "touch"),
+    ("0xc1342574", "liquidate"),
+    ("0xa9059cbb", "transfer"),
+    ("0x23b872dd", "transferFrom"),
+    ("0xcbfdd7e1", "transferFromMax"),
+    ("0x095ea7b3", "approve"),
+    ("0x2b5335c3", "convertFees"),
+    ("0x869e50c7", "disableController"),
+];
+
+/// EVault function name of a 4-byte selector, if known
+pub fn decode_selector(selector: &str) -> Option<&'static str> {
+    let selector = selector.to_lowercase();
+    let selector = selector.get(..10)?;
+    EVAULT_SELECTORS
+        .iter()
+        .find(|(known, _)| *known == selector)
+        .map(|(_, name)| *name)
+}
+
+/// Selects which calls [`fetch_calls_with_context`] returns
+#[derive(Debug, Clone, Default)]
+pub struct CallFilter {
+    pub vault: Option<String>,
+    /// Owner of the sub-accounts involved
+    pub main_address: Option<String>,
+    /// Account among the call's accounts
+    pub account: Option<String>,
+    /// 4-byte selector, e.g. `0x4b3fd148` for `borrow`
+    pub selector: Option<String>,
+    /// Inclusive lower bound on the block timestamp
+    pub from: Option<i64>,
+    /// Exclusive upper bound on the block timestamp
+    pub to: Option<i64>,
+    /// Maximum number of transactions, the newest ones, whose calls are all returned
+    pub limit: Option<usize>,
+}
+
+impl CallFilter {
+    fn to_where(&self) -> Value {
+        let mut filter = Map::new();
+        if let Some(vault) = &self.vault {
+            filter.insert("vault".to_string(), json!(vault.to_lowercase()));
+        }
+        if let Some(main_address) = &self.main_address {
+            filter.insert(
+                "mainAddress".to_string(),
+                json!(main_address.to_lowercase()),
+            );
+        }
+        if let Some(account) = &self.account {
+            filter.insert(
+                "accounts_contains".to_string(),
+                json!([account.to_lowercase()]),
+            );
+        }
+        if let Some(selector) = &self.selector {
+            filter.insert("selector".to_string(), json!(selector.to_lowercase()));
+        }
+        if let Some(from) = self.from {
+            filter.insert("blockTimestamp_gte".to_string(), json!(from.to_string()));
+        }
+        if let Some(to) = self.to {
+            filter.insert("blockTimestamp_lt".to_string(), json!(to.to_string()));
+        }
+        Value::Object(filter)
+    }
+}
+
+/// A call to a vault made through the EVC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallWithContext {
+    /// Transaction hash followed by the vault address
+    pub id: String,
+    pub selector: String,
+    pub vault: String,
+    #[serde(rename = "mainAddress")]
+    pub main_address: String,
+    pub accounts: Vec<String>,
+    #[serde(rename = "type")]
+    pub call_type: String,
+    pub evc: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "blockTimestamp")]
+    pub block_timestamp: String,
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: String,
+}
+
+impl CallWithContext {
+    /// EVault function called, if the selector is known
+    pub fn function(&self) -> Option<&'static str> {
+        decode_selector(&self.selector)
+    }
+}
+
+/// What a batch of calls in one transaction amounts to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchPattern {
+    /// A liquidation, possibly alongside other calls
+    Liquidation,
+    /// Borrowing and depositing in the same transaction, i.e. looping leverage
+    Leverage,
+    /// Withdrawing collateral and repaying in the same transaction
+    Deleverage,
+    /// Withdrawing from one vault and depositing in another without borrowing
+    CollateralSwap,
+    /// A single call
+    Single,
+    Other,
+}
+
+/// Calls made in one transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallBatch {
+    pub transaction_hash: String,
+    pub block_number: String,
+    pub block_timestamp: String,
+    pub calls: Vec<CallWithContext>,
+    pub pattern: BatchPattern,
+}
+
+impl CallBatch {
+    /// Decoded function names, `unknown` for unrecognized selectors
+    pub fn functions(&self) -> Vec<&'static str> {
+        self.calls
+            .iter()
+            .map(|call| call.function().unwrap_or("unknown"))
+            .collect()
+    }
+}
+
+/// Fetch calls matching `filter`, oldest first. With a limit, the newest calls are paged
+/// block by block until they span `limit` transactions, and every matching call of those
+/// transactions is then fetched so that [`group_batches`] sees whole transactions.
+pub async fn fetch_calls_with_context(
+    client: &GraphClient,
+    filter: &CallFilter,
+) -> Result<Vec<CallWithContext>> {
+    let query = r#"
+    query EulerCallsWithContext($first: Int!, $lastId: Bytes!, $where: CallWithContext_filter!, $orderBy: CallWithContext_orderBy!, $orderDirection: OrderDirection!) {
+        callWithContexts(
+            first: $first,
+            orderBy: $orderBy,
+            orderDirection: $orderDirection,
+            where: { and: [{ id_gt: $lastId }, $where] }
+        ) {
+            id
+            selector
+            vault
+            mainAddress
+            accounts
+            type
+            evc
+            blockNumber
+            blockTimestamp
+            transactionHash
+        }
+    }
+    "#;
+
+    let mut filter_where = filter.to_where();
+    if let Some(limit) = filter.limit {
+        let mut newest = Vec::new();
+        let mut before: Option<u64> = None;
+        loop {
+            let mut page_where = filter_where.clone();
+            if let Some(before) = before {
+                page_where["blockNumber_lt"] = json!(before.to_string());
+            }
+            let page: Vec<CallWithContext> = client
+                .query_newest(
+                    query,
+                    "callWithContexts",
+                    "blockNumber",
+                    json!({ "where": page_where }),
+                    Some(MAX_PAGE_SIZE),
+                )
+                .await?;
+            let (complete, next) = complete_blocks(page, MAX_PAGE_SIZE);
+            newest.extend(complete);
+            if next.is_none() || newest_transactions(&newest, limit).len() >= limit {
+                break;
+            }
+            before = next;
+        }
+        filter_where["transactionHash_in"] = json!(newest_transactions(&newest, limit));
+    }
+
+    let mut calls: Vec<CallWithContext> = client
+        .query_newest(
+            query,
+            "callWithContexts",
+            "blockNumber",
+            json!({ "where": filter_where }),
+            None,
+        )
+        .await?;
+    calls.sort_by_key(|call| call.block_number.parse::<u64>().unwrap_or(0));
+    Ok(calls)
+}
+
+/// Calls of the blocks a page ordered by block number descending holds in full, and the
+/// exclusive upper bound on the block number of the next page, `None` once the data runs out.
+/// The oldest block of a full page may continue on the next page, so it is left to that page,
+/// unless it fills the whole page.
+fn complete_blocks(
+    page: Vec<CallWithContext>,
+    page_size: usize,
+) -> (Vec<CallWithContext>, Option<u64>) {
+    if page.len() < page_size {
+        return (page, None);
+    }
+    let block = |call: &CallWithContext| call.block_number.parse::<u64>().unwrap_or(0);
+    let oldest = page.iter().map(block).min().unwrap_or(0);
+    if page.iter().all(|call| block(call) == oldest) {
+        return (page, Some(oldest));
+    }
+    let complete = page
+        .into_iter()
+        .filter(|call| block(call) > oldest)
+        .collect();
+    (complete, Some(oldest + 1))
+}
+
+/// Hashes of the `limit` newest transactions among `calls`, newest first
+fn newest_transactions(calls: &[CallWithContext], limit: usize) -> Vec<String> {
+    let mut transactions: Vec<(u64, &str)> = calls
+        .iter()
+        .map(|call| {
+            (
+                call.block_number.parse::<u64>().unwrap_or(0),
+                call.transaction_hash.as_str(),
+            )
+        })
+        .collect();
+    transactions.sort_by(|a, b| b.cmp(a));
+    transactions.dedup();
+    transactions
+        .into_iter()
+        .take(limit)
+        .map(|(_, hash)| hash.to_string())
+        .collect()
+}
+
+/// Group calls by transaction, oldest first. Only calls passed in are grouped, so fetch with a
+/// filter that returns every call of the transactions of interest, e.g. by main address.
+pub fn group_batches(calls: Vec<CallWithContext>) -> Vec<CallBatch> {
+    let mut by_transaction: BTreeMap<(u64, String), Vec<CallWithContext>> = BTreeMap::new();
+    for call in calls {
+        let block = call.block_number.parse::<u64>().unwrap_or(0);
+        by_transaction
+            .entry((block, call.transaction_hash.clone()))
+            .or_default()
+            .push(call);
+    }
+
+    by_transaction
+        .into_iter()
+        .map(|((_, transaction_hash), calls)| CallBatch {
+            block_number: calls[0].block_number.clone(),
+            block_timestamp: calls[0].block_timestamp.clone(),
+            pattern: classify(&calls),
+            transaction_hash,
+            calls,
+        })
+        .collect()
+}
+
+fn classify(calls: &[CallWithContext]) -> BatchPattern {
+    let has = |names: &[&str]| {
+        calls
+            .iter()
+            .any(|call| call.function().is_some_and(|f| names.contains(&f)))
+    };
+    let deposits = ["deposit", "mint", "skim"];
+    let withdraws = ["withdraw", "redeem"];
+
+    if has(&["liquidate"]) {
+        BatchPattern::Liquidation
+    } else if calls.len() == 1 {
+        BatchPattern::Single
+    } else if has(&["borrow"]) && has(&deposits) {
+        BatchPattern::Leverage
+    } else if has(&["repay", "repayWithShares"]) && has(&withdraws) {
+        BatchPattern::Deleverage
+    } else if has(&withdraws) && has(&deposits) {
+        BatchPattern::CollateralSwap
+    } else {
+        BatchPattern::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(tx: &str, block: &str, vault: &str, selector: &str) -> CallWithContext {
+        CallWithContext {
+            id: format!("{}{}", tx, vault),
+            selector: selector.to_string(),
+            vault: vault.to_string(),
+            main_address: "0xowner".to_string(),
+            accounts: vec!["0xowner".to_string()],
+            call_type: "call".to_string(),
+            evc: "0xevc".to_string(),
+            block_number: block.to_string(),
+            block_timestamp: block.to_string(),
+            transaction_hash: tx.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_decode_selector() {
+        assert_eq!(decode_selector("0x4B3FD148"), Some("borrow"));
+        assert_eq!(decode_selector("0xc1342574"), Some("liquidate"));
+        assert_eq!(decode_selector("0xdeadbeef"), None);
+    }
+
+    #[test]
+    fn test_group_batches_classifies_patterns() {
+        let calls = vec![
+            call("0xtx2", "20", "0xusdc", "0xb460af94"),
+            call("0xtx1", "10", "0xusdc", "0x4b3fd148"),
+            call("0xtx2", "20", "0xusdt", "0x6e553f65"),
+            call("0xtx1", "10", "0xweth", "0x6e553f65"),
+            call("0xtx3", "30", "0xweth", "0xdeadbeef"),
+        ];
+
+        let batches = group_batches(calls);
+
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[0].transaction_hash, "0xtx1");
+        assert_eq!(batches[0].pattern, BatchPattern::Leverage);
+        assert_eq!(batches[1].pattern, BatchPattern::CollateralSwap);
+        assert_eq!(batches[2].pattern, BatchPattern::Single);
+        assert_eq!(batches[2].functions(), vec!["unknown"]);
+        assert_eq!(
+            newest_transactions(
+                &[
+                    call("0xtx2", "20", "0xusdc", "0xb460af94"),
+                    call("0xtx3", "30", "0xweth", "0xdeadbeef"),
+                    call("0xtx2", "20", "0xusdt", "0x6e553f65"),
+                    call("0xtx1", "10", "0xusdc", "0x4b3fd148")
+                ],
+                2
+            ),
+            vec!["0xtx3", "0xtx2"]
+        );
+    }
+
+    #[test]
+    fn test_complete_blocks_leaves_partial_block_to_next_page() {
+        let page = vec![
+            call("0xtx3", "30", "0xweth", "0xdeadbeef"),
+            call("0xtx2", "20", "0xusdc", "0xb460af94"),
+            call("0xtx1", "20", "0xusdt", "0x6e553f65"),
+        ];
+
+        let (complete, next) = complete_blocks(page.clone(), 4);
+        assert_eq!(complete.len(), 3);
+        assert_eq!(next, None);
+
+        let (complete, next) = complete_blocks(page, 3);
+        assert_eq!(complete.len(), 1);
+        assert_eq!(complete[0].transaction_hash, "0xtx3");
+        assert_eq!(next, Some(21));
+
+        let single_block = vec![
+            call("0xtx2", "20", "0xusdc", "0xb460af94"),
+            call("0xtx1", "20", "0xusdt", "0x6e553f65"),
+        ];
+        let (complete, next) = complete_blocks(single_block, 2);
+        assert_eq!(complete.len(), 2);
+        assert_eq!(next, Some(20));
+    }
+}
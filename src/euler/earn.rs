@@ -0,0 +1,490 @@
+//! Euler Earn aggregator vaults, the Euler counterpart to MetaMorpho vaults.
+//!
+//! `DeployEulerEarn` records each Earn vault with its owner and asset. The Earn deposit,
+//! withdraw and transfer entities do not record which Earn vault emitted them, so they are
+//! attributed through the underlying EVaults: an Earn vault allocates deposits to and serves
+//! withdrawals from its strategies within the same transaction, which emits an EVault
+//! `Deposit` or `Withdraw` owned by the Earn vault. An Earn event in such a transaction is
+//! attributed to the Earn vault owning the strategy flow nearest to it by log index, so a
+//! transaction touching several Earn vaults splits between them. Deposits left idle and
+//! transfers between holders outside such transactions are missed, so the history is partial
+//! and share balances cannot be derived from it. For that reason no holder list is provided,
+//! and daily net flows undercount deposits and withdrawals served from idle assets.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::status::log_index;
+use crate::client::GraphClient;
+use crate::numeric::to_f64;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Number of transaction hashes sent per `transactionHash_in` lookup
+const TRANSACTION_BATCH_SIZE: usize = 500;
+
+/// An Earn vault as recorded by its `DeployEulerEarn` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EarnVault {
+    pub id: String,
+    #[serde(rename = "_owner")]
+    pub owner: String,
+    /// Address of the Earn vault
+    #[serde(rename = "_eulerEarnVault")]
+    pub vault: String,
+    /// Address of the underlying asset
+    #[serde(rename = "_asset")]
+    pub asset: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "blockTimestamp")]
+    pub block_timestamp: String,
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EarnDeposit {
+    pub id: String,
+    pub sender: String,
+    pub owner: String,
+    pub assets: String,
+    pub shares: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "blockTimestamp")]
+    pub block_timestamp: String,
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EarnWithdraw {
+    pub id: String,
+    pub sender: String,
+    pub receiver: String,
+    pub owner: String,
+    pub assets: String,
+    pub shares: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "blockTimestamp")]
+    pub block_timestamp: String,
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: String,
+}
+
+/// A transfer of Earn vault shares; mints come from and burns go to the zero address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EarnTransfer {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+    pub value: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "blockTimestamp")]
+    pub block_timestamp: String,
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: String,
+}
+
+/// Deposits and withdrawals of an Earn vault during one day, in native units of its asset
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EarnDailyFlow {
+    /// Start of the day, in seconds since the Unix epoch
+    pub day: i64,
+    pub inflow: f64,
+    pub outflow: f64,
+    /// Net of the attributed flows only, see [`PartialEarnHistory::daily_flows`]
+    pub net: f64,
+}
+
+/// Share price of an Earn vault implied by a deposit or withdrawal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EarnSharePrice {
+    pub timestamp: i64,
+    pub block_number: String,
+    /// Assets per share, in native units of each
+    pub share_price: f64,
+}
+
+/// Deposits, withdrawals and share transfers attributed to an Earn vault. Only events in
+/// transactions that moved assets to or from the vault's strategies are included, so idle
+/// deposits and plain share transfers are missing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialEarnHistory {
+    pub vault: String,
+    pub deposits: Vec<EarnDeposit>,
+    pub withdraws: Vec<EarnWithdraw>,
+    pub transfers: Vec<EarnTransfer>,
+}
+
+impl PartialEarnHistory {
+    /// Attributed deposits and withdrawals per day, oldest first. The flows are partial:
+    /// deposits left idle and withdrawals served from idle assets are not attributed to the
+    /// vault, so `net` is not the vault's actual net flow.
+    pub fn daily_flows(&self) -> Vec<EarnDailyFlow> {
+        let mut days: BTreeMap<i64, EarnDailyFlow> = BTreeMap::new();
+        for deposit in &self.deposits {
+            day_entry(&mut days, &deposit.block_timestamp).inflow += to_f64(&deposit.assets);
+        }
+        for withdraw in &self.withdraws {
+            day_entry(&mut days, &withdraw.block_timestamp).outflow += to_f64(&withdraw.assets);
+        }
+
+        days.into_values()
+            .map(|mut flow| {
+                flow.net = flow.inflow - flow.outflow;
+                flow
+            })
+            .collect()
+    }
+
+    /// Assets per share of every deposit and withdrawal, oldest first
+    pub fn share_prices(&self) -> Vec<EarnSharePrice> {
+        let deposits = self
+            .deposits
+            .iter()
+            .filter_map(|d| share_price(&d.block_timestamp, &d.block_number, &d.assets, &d.shares));
+        let withdraws = self
+            .withdraws
+            .iter()
+            .filter_map(|w| share_price(&w.block_timestamp, &w.block_number, &w.assets, &w.shares));
+
+        let mut prices: Vec<EarnSharePrice> = deposits.chain(withdraws).collect();
+        prices.sort_by_key(|price| price.timestamp);
+        prices
+    }
+}
+
+fn share_price(
+    timestamp: &str,
+    block_number: &str,
+    assets: &str,
+    shares: &str,
+) -> Option<EarnSharePrice> {
+    let shares = to_f64(shares);
+    if shares <= 0.0 {
+        return None;
+    }
+    Some(EarnSharePrice {
+        timestamp: timestamp.parse().unwrap_or(0),
+        block_number: block_number.to_string(),
+        share_price: to_f64(assets) / shares,
+    })
+}
+
+fn day_entry<'a>(
+    days: &'a mut BTreeMap<i64, EarnDailyFlow>,
+    timestamp: &str,
+) -> &'a mut EarnDailyFlow {
+    let day = timestamp.parse::<i64>().unwrap_or(0) / SECONDS_PER_DAY * SECONDS_PER_DAY;
+    days.entry(day).or_insert_with(|| EarnDailyFlow {
+        day,
+        ..Default::default()
+    })
+}
+
+/// An EVault `Deposit` or `Withdraw`, a strategy flow when its owner is an Earn vault
+#[derive(Debug, Clone, Deserialize)]
+struct StrategyFlow {
+    id: String,
+    #[serde(default)]
+    owner: String,
+    #[serde(rename = "transactionHash")]
+    transaction_hash: String,
+}
+
+/// An Earn event attributed through the strategy flows of its transaction
+trait EarnEvent {
+    fn id(&self) -> &str;
+    fn transaction_hash(&self) -> &str;
+}
+
+impl EarnEvent for EarnDeposit {
+    fn id(&self) -> &str {
+        &self.id
+    }
+    fn transaction_hash(&self) -> &str {
+        &self.transaction_hash
+    }
+}
+
+impl EarnEvent for EarnWithdraw {
+    fn id(&self) -> &str {
+        &self.id
+    }
+    fn transaction_hash(&self) -> &str {
+        &self.transaction_hash
+    }
+}
+
+impl EarnEvent for EarnTransfer {
+    fn id(&self) -> &str {
+        &self.id
+    }
+    fn transaction_hash(&self) -> &str {
+        &self.transaction_hash
+    }
+}
+
+/// Fetch every deployed Earn vault, oldest first
+pub async fn fetch_earn_vaults(client: &GraphClient) -> Result<Vec<EarnVault>> {
+    let query = r#"
+    query EulerEarnVaults($first: Int!, $lastId: Bytes!) {
+        deployEulerEarns(first: $first, orderBy: id, orderDirection: asc, where: { id_gt: $lastId }) {
+            id
+            _owner
+            _eulerEarnVault
+            _asset
+            blockNumber
+            blockTimestamp
+            transactionHash
+        }
+    }
+    "#;
+
+    let mut vaults: Vec<EarnVault> = client
+        .query_paginated(query, "deployEulerEarns", json!({}), None)
+        .await?;
+    vaults.sort_by_key(|vault| vault.block_number.parse::<u64>().unwrap_or(0));
+    Ok(vaults)
+}
+
+/// Fetch the deposits, withdrawals and share transfers of an Earn vault with a block
+/// timestamp in `range`, as far as they can be attributed
+pub async fn fetch_earn_history(
+    client: &GraphClient,
+    vault: &str,
+    range: Range<i64>,
+) -> Result<PartialEarnHistory> {
+    let vault = vault.to_lowercase();
+    let variables = json!({
+        "vault": vault,
+        "from": range.start.to_string(),
+        "to": range.end.to_string(),
+    });
+
+    let mut transactions = Vec::new();
+    for collection in ["deposits", "withdraws"] {
+        let query = format!(
+            r#"
+    query EulerEarnStrategyFlows($first: Int!, $lastId: Bytes!, $vault: Bytes!, $from: BigInt!, $to: BigInt!) {{
+        {}(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: {{ id_gt: $lastId, owner: $vault, blockTimestamp_gte: $from, blockTimestamp_lt: $to }}
+        ) {{
+            id
+            transactionHash
+        }}
+    }}
+    "#,
+            collection
+        );
+        let flows: Vec<StrategyFlow> = client
+            .query_paginated(&query, collection, variables.clone(), None)
+            .await?;
+        transactions.extend(flows.into_iter().map(|flow| flow.transaction_hash));
+    }
+    transactions.sort();
+    transactions.dedup();
+
+    let earn_vaults: HashSet<String> = fetch_earn_vaults(client)
+        .await?
+        .into_iter()
+        .map(|earn| earn.vault.to_lowercase())
+        .collect();
+
+    let mut history = PartialEarnHistory {
+        vault,
+        deposits: Vec::new(),
+        withdraws: Vec::new(),
+        transfers: Vec::new(),
+    };
+    for batch in transactions.chunks(TRANSACTION_BATCH_SIZE) {
+        let mut flows: Vec<StrategyFlow> =
+            fetch_in_transactions(client, "deposits", FLOW_FIELDS, batch).await?;
+        flows.extend(
+            fetch_in_transactions::<StrategyFlow>(client, "withdraws", FLOW_FIELDS, batch).await?,
+        );
+        flows.retain(|flow| earn_vaults.contains(&flow.owner.to_lowercase()));
+
+        history.deposits.extend(attribute(
+            fetch_in_transactions(client, "eulerEarnDeposits", DEPOSIT_FIELDS, batch).await?,
+            &flows,
+            &history.vault,
+        ));
+        history.withdraws.extend(attribute(
+            fetch_in_transactions(client, "eulerEarnWithdraws", WITHDRAW_FIELDS, batch).await?,
+            &flows,
+            &history.vault,
+        ));
+        history.transfers.extend(attribute(
+            fetch_in_transactions(client, "eulerEarnTransfers", TRANSFER_FIELDS, batch).await?,
+            &flows,
+            &history.vault,
+        ));
+    }
+    Ok(history)
+}
+
+/// Keep the events whose nearest strategy flow in the same transaction, by log index, is
+/// owned by `vault`. Events equally near to flows of different Earn vaults are dropped.
+fn attribute<T: EarnEvent>(events: Vec<T>, flows: &[StrategyFlow], vault: &str) -> Vec<T> {
+    events
+        .into_iter()
+        .filter(|event| {
+            let index = log_index(event.id());
+            let mut nearest: Vec<(i32, &str)> = flows
+                .iter()
+                .filter(|flow| flow.transaction_hash == event.transaction_hash())
+                .map(|flow| ((log_index(&flow.id) - index).abs(), flow.owner.as_str()))
+                .collect();
+            nearest.sort();
+            match nearest.as_slice() {
+                [] => false,
+                [(_, owner)] => owner.eq_ignore_ascii_case(vault),
+                [(distance, owner), (next_distance, next_owner), ..] => {
+                    owner.eq_ignore_ascii_case(vault)
+                        && (distance < next_distance || next_owner.eq_ignore_ascii_case(vault))
+                }
+            }
+        })
+        .collect()
+}
+
+const DEPOSIT_FIELDS: &str =
+    "id sender owner assets shares blockNumber blockTimestamp transactionHash";
+const WITHDRAW_FIELDS: &str =
+    "id sender receiver owner assets shares blockNumber blockTimestamp transactionHash";
+const TRANSFER_FIELDS: &str = "id from to value blockNumber blockTimestamp transactionHash";
+const FLOW_FIELDS: &str = "id owner transactionHash";
+
+async fn fetch_in_transactions<T: serde::de::DeserializeOwned>(
+    client: &GraphClient,
+    collection: &str,
+    fields: &str,
+    transactions: &[String],
+) -> Result<Vec<T>> {
+    let query = format!(
+        r#"
+    query EulerEarnEvents($first: Int!, $lastId: Bytes!, $transactions: [Bytes!]!) {{
+        {}(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: {{ id_gt: $lastId, transactionHash_in: $transactions }}
+        ) {{
+            {}
+        }}
+    }}
+    "#,
+        collection, fields
+    );
+
+    client
+        .query_paginated(
+            &query,
+            collection,
+            json!({ "transactions": transactions }),
+            None,
+        )
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Event id in the subgraph encoding: transaction hash then little-endian log index
+    fn event_id(tx: &str, log_index: i32) -> String {
+        let index: String = log_index
+            .to_le_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("{}{}", tx, index)
+    }
+
+    fn deposit(day: i64, assets: &str, shares: &str) -> EarnDeposit {
+        EarnDeposit {
+            id: format!("0xdeposit{}", day),
+            sender: "0xalice".to_string(),
+            owner: "0xalice".to_string(),
+            assets: assets.to_string(),
+            shares: shares.to_string(),
+            block_number: day.to_string(),
+            block_timestamp: (day * SECONDS_PER_DAY + 60).to_string(),
+            transaction_hash: "0xtx".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_history_flows_and_share_price() {
+        let history = PartialEarnHistory {
+            vault: "0xearn".to_string(),
+            deposits: vec![deposit(2, "110", "100"), deposit(1, "100", "100")],
+            withdraws: vec![EarnWithdraw {
+                id: "0xwithdraw".to_string(),
+                sender: "0xbob".to_string(),
+                receiver: "0xbob".to_string(),
+                owner: "0xbob".to_string(),
+                assets: "33".to_string(),
+                shares: "30".to_string(),
+                block_number: "3".to_string(),
+                block_timestamp: (2 * SECONDS_PER_DAY + 120).to_string(),
+                transaction_hash: "0xtx".to_string(),
+            }],
+            transfers: Vec::new(),
+        };
+
+        let flows = history.daily_flows();
+        assert_eq!(flows.len(), 2);
+        assert_eq!(flows[0].inflow, 100.0);
+        assert_eq!(flows[1].net, 110.0 - 33.0);
+
+        let prices = history.share_prices();
+        assert_eq!(prices.len(), 3);
+        assert_eq!(prices[0].share_price, 1.0);
+        assert!((prices[2].share_price - 1.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_attribute_splits_transaction_touching_two_vaults() {
+        let tx = "0xaa";
+        let flow = |log_index: i32, owner: &str| StrategyFlow {
+            id: event_id(tx, log_index),
+            owner: owner.to_string(),
+            transaction_hash: tx.to_string(),
+        };
+        // A router deposits into Earn vault A, whose strategy deposit follows at log 3, then
+        // into Earn vault B, whose strategy deposit follows at log 9
+        let flows = vec![flow(3, "0xEarnA"), flow(9, "0xearnb")];
+        let deposits = || {
+            [1, 7]
+                .into_iter()
+                .map(|log_index| EarnDeposit {
+                    id: event_id(tx, log_index),
+                    transaction_hash: tx.to_string(),
+                    ..deposit(1, "100", "100")
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let a = attribute(deposits(), &flows, "0xearna");
+        let b = attribute(deposits(), &flows, "0xearnb");
+
+        assert_eq!(a.len(), 1);
+        assert_eq!(a[0].id, event_id(tx, 1));
+        assert_eq!(b.len(), 1);
+        assert_eq!(b[0].id, event_id(tx, 7));
+        assert!(attribute(deposits(), &flows[..0], "0xearna").is_empty());
+    }
+}
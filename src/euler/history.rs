@@ -0,0 +1,199 @@
+//! Euler vault status history downsampled to a fixed resolution.
+//!
+//! A `VaultStatus` row is written on every interaction with a vault, so the rows of one vault
+//! form a high-resolution history of its cash, borrows, shares and interest rate. Each bucket
+//! of the series holds the last status within it.
+//!
+//! Supply APY uses the interest fee derived from consecutive buckets, carried forward when it
+//! cannot be derived and starting from [`DEFAULT_INTEREST_FEE`].
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::rates::{derive_interest_fee, DEFAULT_INTEREST_FEE};
+use super::status::{fetch_states_for_transactions, VaultState};
+use crate::client::GraphClient;
+use crate::numeric::to_f64;
+
+const SECONDS_PER_HOUR: i64 = 60 * 60;
+const SECONDS_PER_DAY: i64 = 24 * SECONDS_PER_HOUR;
+
+/// Bucket size of a vault history series
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Resolution {
+    Hourly,
+    Daily,
+}
+
+impl Resolution {
+    pub fn seconds(self) -> i64 {
+        match self {
+            Resolution::Hourly => SECONDS_PER_HOUR,
+            Resolution::Daily => SECONDS_PER_DAY,
+        }
+    }
+}
+
+/// State of a vault at the end of a bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultHistoryPoint {
+    /// Start of the bucket, in seconds since the Unix epoch
+    pub bucket: i64,
+    pub block_number: String,
+    pub block_timestamp: i64,
+    /// Borrows divided by total assets, between 0 and 1
+    pub utilization: f64,
+    /// As a fraction, e.g. 0.05 for 5%
+    pub borrow_apy: f64,
+    /// As a fraction, after the interest fee
+    pub supply_apy: f64,
+    /// Share of borrow interest taken as fees, between 0 and 1
+    pub interest_fee: f64,
+    /// Cash plus borrows, in native units of the vault asset
+    pub total_assets: f64,
+    /// In native units of the vault asset
+    pub total_borrows: f64,
+    /// In native units of the vault asset
+    pub cash: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct VaultCall {
+    #[serde(rename = "transactionHash")]
+    transaction_hash: String,
+}
+
+/// Fetch the status history of a vault with a block timestamp in `[from, to)`, downsampled to
+/// `resolution`, oldest first. Buckets without any interaction are omitted.
+pub async fn fetch_vault_history(
+    client: &GraphClient,
+    vault: &str,
+    from: i64,
+    to: i64,
+    resolution: Resolution,
+) -> Result<Vec<VaultHistoryPoint>> {
+    let query = r#"
+    query EulerVaultCalls($first: Int!, $lastId: Bytes!, $vault: Bytes!, $from: BigInt!, $to: BigInt!) {
+        callWithContexts(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, vault: $vault, blockTimestamp_gte: $from, blockTimestamp_lt: $to }
+        ) {
+            id
+            transactionHash
+        }
+    }
+    "#;
+
+    let vault = vault.to_lowercase();
+    let variables = json!({
+        "vault": vault,
+        "from": from.to_string(),
+        "to": to.to_string(),
+    });
+
+    let calls: Vec<VaultCall> = client
+        .query_paginated(query, "callWithContexts", variables, None)
+        .await?;
+    let mut transactions: Vec<String> = calls.into_iter().map(|c| c.transaction_hash).collect();
+    transactions.sort();
+    transactions.dedup();
+
+    let states: Vec<VaultState> = fetch_states_for_transactions(client, &transactions)
+        .await?
+        .into_iter()
+        .filter(|state| state.vault == vault)
+        .collect();
+    Ok(downsample(states, resolution))
+}
+
+/// Keep the last state of each bucket, oldest bucket first
+fn downsample(states: Vec<VaultState>, resolution: Resolution) -> Vec<VaultHistoryPoint> {
+    let step = resolution.seconds();
+    let mut buckets: BTreeMap<i64, VaultState> = BTreeMap::new();
+    for state in states {
+        let bucket = state.block_timestamp() / step * step;
+        let later = buckets.get(&bucket).is_none_or(|current| {
+            (state.block_timestamp(), state.log_index())
+                > (current.block_timestamp(), current.log_index())
+        });
+        if later {
+            buckets.insert(bucket, state);
+        }
+    }
+
+    let mut interest_fee = DEFAULT_INTEREST_FEE;
+    let mut previous: Option<VaultState> = None;
+    let mut series = Vec::with_capacity(buckets.len());
+    for (bucket, state) in buckets {
+        if let Some(fee) = previous
+            .as_ref()
+            .and_then(|before| derive_interest_fee(before, &state))
+        {
+            interest_fee = fee;
+        }
+        series.push(VaultHistoryPoint {
+            bucket,
+            block_number: state.block_number.clone(),
+            block_timestamp: state.block_timestamp(),
+            utilization: state.utilization(),
+            borrow_apy: state.borrow_apy(),
+            supply_apy: state.supply_apy(interest_fee),
+            interest_fee,
+            total_assets: state.total_assets(),
+            total_borrows: to_f64(&state.total_borrows),
+            cash: to_f64(&state.cash),
+        });
+        previous = Some(state);
+    }
+    series
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(timestamp: i64, cash: &str, total_borrows: &str) -> VaultState {
+        VaultState {
+            vault: "0xvault".to_string(),
+            id: format!("0x{:064x}00000000", timestamp),
+            total_shares: "0".to_string(),
+            total_borrows: total_borrows.to_string(),
+            accumulated_fees: "0".to_string(),
+            cash: cash.to_string(),
+            interest_accumulator: "0".to_string(),
+            interest_rate: "0".to_string(),
+            timestamp: timestamp.to_string(),
+            block_number: timestamp.to_string(),
+            block_timestamp: timestamp.to_string(),
+            transaction_hash: "0xtx".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_downsample_keeps_last_state_per_bucket() {
+        let hour = SECONDS_PER_HOUR;
+        let states = vec![
+            state(2 * hour + 10, "100", "300"),
+            state(hour + 5, "100", "100"),
+            state(hour + 50, "50", "150"),
+        ];
+
+        let series = downsample(states, Resolution::Hourly);
+
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].bucket, hour);
+        assert_eq!(series[0].block_timestamp, hour + 50);
+        assert_eq!(series[0].total_assets, 200.0);
+        assert_eq!(series[0].utilization, 0.75);
+        assert_eq!(series[1].utilization, 0.75);
+        assert_eq!(
+            downsample(vec![state(hour, "1", "1")], Resolution::Daily)[0].bucket,
+            0
+        );
+    }
+}
@@ -0,0 +1,477 @@
+//! Euler liquidations correlated with the debt they socialized.
+//!
+//! `Liquidate` and `DebtSocialized` are emitted by the liability vault but do not record it.
+//! A liquidation pulls the violator's debt, which emits a `Repay` for the violator in the
+//! liability vault within the same transaction, so that `Repay` identifies the vault. When the
+//! violator has no collateral left, the remaining debt is socialized with a `DebtSocialized`
+//! for the violator in the same transaction, which marks the liquidation as bad debt.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::client::GraphClient;
+use crate::numeric::to_f64;
+
+/// Number of transaction hashes sent per correlation lookup
+const TRANSACTION_BATCH_SIZE: usize = 500;
+
+/// Selects which liquidations [`fetch_liquidations`] returns
+#[derive(Debug, Clone, Default)]
+pub struct LiquidationFilter {
+    pub liquidator: Option<String>,
+    pub violator: Option<String>,
+    /// Vault whose shares were seized
+    pub collateral: Option<String>,
+    /// Inclusive lower bound on the block timestamp
+    pub from: Option<i64>,
+    /// Exclusive upper bound on the block timestamp
+    pub to: Option<i64>,
+    /// Maximum number of liquidations
+    pub limit: Option<usize>,
+}
+
+impl LiquidationFilter {
+    fn to_where(&self) -> Value {
+        let mut filter = Map::new();
+        if let Some(liquidator) = &self.liquidator {
+            filter.insert("liquidator".to_string(), json!(liquidator.to_lowercase()));
+        }
+        if let Some(violator) = &self.violator {
+            filter.insert("violator".to_string(), json!(violator.to_lowercase()));
+        }
+        if let Some(collateral) = &self.collateral {
+            filter.insert("collateral".to_string(), json!(collateral.to_lowercase()));
+        }
+        if let Some(from) = self.from {
+            filter.insert("blockTimestamp_gte".to_string(), json!(from.to_string()));
+        }
+        if let Some(to) = self.to {
+            filter.insert("blockTimestamp_lt".to_string(), json!(to.to_string()));
+        }
+        Value::Object(filter)
+    }
+}
+
+/// Selects which debt socializations [`fetch_debt_socializations`] returns
+#[derive(Debug, Clone, Default)]
+pub struct DebtSocializationFilter {
+    /// Account whose debt was socialized
+    pub account: Option<String>,
+    /// Inclusive lower bound on the block timestamp
+    pub from: Option<i64>,
+    /// Exclusive upper bound on the block timestamp
+    pub to: Option<i64>,
+}
+
+impl DebtSocializationFilter {
+    fn to_where(&self) -> Value {
+        let mut filter = Map::new();
+        if let Some(account) = &self.account {
+            filter.insert("account".to_string(), json!(account.to_lowercase()));
+        }
+        if let Some(from) = self.from {
+            filter.insert("blockTimestamp_gte".to_string(), json!(from.to_string()));
+        }
+        if let Some(to) = self.to {
+            filter.insert("blockTimestamp_lt".to_string(), json!(to.to_string()));
+        }
+        Value::Object(filter)
+    }
+}
+
+/// Debt of an account spread over the depositors of a vault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebtSocialization {
+    pub id: String,
+    pub account: String,
+    /// Socialized debt, in native units of the vault asset
+    pub assets: String,
+    /// Liability vault, when a repay in the same transaction identifies it
+    #[serde(default)]
+    pub vault: Option<String>,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "blockTimestamp")]
+    pub block_timestamp: String,
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: String,
+}
+
+/// An Euler liquidation, joined with its liability vault and any socialized debt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Liquidation {
+    pub id: String,
+    pub liquidator: String,
+    pub violator: String,
+    /// Vault whose shares were seized
+    pub collateral: String,
+    /// Debt taken over by the liquidator, in native units of the liability asset
+    #[serde(rename = "repayAssets")]
+    pub repay_assets: String,
+    /// Collateral shares transferred to the liquidator
+    #[serde(rename = "yieldBalance")]
+    pub yield_balance: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "blockTimestamp")]
+    pub block_timestamp: String,
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: String,
+    /// Liability vault, when a repay in the same transaction identifies it
+    #[serde(default)]
+    pub vault: Option<String>,
+    #[serde(default, rename = "debtSocialized")]
+    pub debt_socialized: Option<DebtSocialization>,
+}
+
+impl Liquidation {
+    /// Whether the liquidation ended with the violator's remaining debt socialized
+    pub fn is_bad_debt(&self) -> bool {
+        self.debt_socialized.is_some()
+    }
+
+    /// Socialized debt in native units of the liability asset, zero if none
+    pub fn socialized_assets(&self) -> f64 {
+        self.debt_socialized
+            .as_ref()
+            .map(|debt| to_f64(&debt.assets))
+            .unwrap_or(0.0)
+    }
+}
+
+/// Liquidation totals for a liability and collateral vault pair or a liquidator. Amounts are
+/// in native units, so they only add up across liquidations of the same liability asset.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LiquidationAggregate {
+    /// `vault/collateral` or the liquidator address
+    pub key: String,
+    pub count: usize,
+    pub repay_assets: f64,
+    pub yield_balance: f64,
+    /// Number of liquidations that ended in socialized debt
+    pub bad_debt_count: usize,
+    pub socialized_assets: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ViolatorRepay {
+    account: String,
+    vault: String,
+    #[serde(rename = "transactionHash")]
+    transaction_hash: String,
+}
+
+/// Fetch liquidations matching `filter`, newest first, joined with their liability vault and
+/// any debt socialized in the same transaction
+pub async fn fetch_liquidations(
+    client: &GraphClient,
+    filter: &LiquidationFilter,
+) -> Result<Vec<Liquidation>> {
+    let query = r#"
+    query EulerLiquidations($first: Int!, $lastId: Bytes!, $where: Liquidate_filter!, $orderBy: Liquidate_orderBy!, $orderDirection: OrderDirection!) {
+        liquidates(
+            first: $first,
+            orderBy: $orderBy,
+            orderDirection: $orderDirection,
+            where: { and: [{ id_gt: $lastId }, $where] }
+        ) {
+            id
+            liquidator
+            violator
+            collateral
+            repayAssets
+            yieldBalance
+            blockNumber
+            blockTimestamp
+            transactionHash
+        }
+    }
+    "#;
+
+    let mut liquidations: Vec<Liquidation> = client
+        .query_newest(
+            query,
+            "liquidates",
+            "blockTimestamp",
+            json!({ "where": filter.to_where() }),
+            filter.limit,
+        )
+        .await?;
+    liquidations.sort_by_key(|l| std::cmp::Reverse(l.block_timestamp.parse::<i64>().unwrap_or(0)));
+    if let Some(limit) = filter.limit {
+        liquidations.truncate(limit);
+    }
+
+    let transactions: Vec<String> = liquidations
+        .iter()
+        .map(|l| l.transaction_hash.clone())
+        .collect();
+    let repays = fetch_repays_in(client, &transactions).await?;
+    let mut socializations = Vec::new();
+    for batch in transactions.chunks(TRANSACTION_BATCH_SIZE) {
+        socializations
+            .extend(fetch_socializations(client, json!({ "transactionHash_in": batch })).await?);
+    }
+
+    Ok(correlate(liquidations, &repays, socializations))
+}
+
+/// Fetch debt socializations matching `filter`, newest first, with their liability vault
+/// when it can be identified
+pub async fn fetch_debt_socializations(
+    client: &GraphClient,
+    filter: &DebtSocializationFilter,
+) -> Result<Vec<DebtSocialization>> {
+    let mut socializations = fetch_socializations(client, filter.to_where()).await?;
+
+    let transactions: Vec<String> = socializations
+        .iter()
+        .map(|s| s.transaction_hash.clone())
+        .collect();
+    let repays = fetch_repays_in(client, &transactions).await?;
+    let vaults = violator_vaults(&repays);
+    for socialization in socializations.iter_mut() {
+        socialization.vault = vaults
+            .get(&(
+                socialization.transaction_hash.as_str(),
+                socialization.account.as_str(),
+            ))
+            .map(|vault| vault.to_string());
+    }
+
+    socializations
+        .sort_by_key(|s| std::cmp::Reverse(s.block_timestamp.parse::<i64>().unwrap_or(0)));
+    Ok(socializations)
+}
+
+async fn fetch_socializations(
+    client: &GraphClient,
+    filter: Value,
+) -> Result<Vec<DebtSocialization>> {
+    let query = r#"
+    query EulerDebtSocializations($first: Int!, $lastId: Bytes!, $where: DebtSocialized_filter!) {
+        debtSocializeds(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { and: [{ id_gt: $lastId }, $where] }
+        ) {
+            id
+            account
+            assets
+            blockNumber
+            blockTimestamp
+            transactionHash
+        }
+    }
+    "#;
+
+    client
+        .query_paginated(query, "debtSocializeds", json!({ "where": filter }), None)
+        .await
+}
+
+async fn fetch_repays_in(
+    client: &GraphClient,
+    transactions: &[String],
+) -> Result<Vec<ViolatorRepay>> {
+    let query = r#"
+    query EulerRepaysByTransaction($first: Int!, $lastId: Bytes!, $transactions: [Bytes!]!) {
+        repays(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, transactionHash_in: $transactions }
+        ) {
+            id
+            account
+            vault
+            transactionHash
+        }
+    }
+    "#;
+
+    let mut repays = Vec::new();
+    for batch in transactions.chunks(TRANSACTION_BATCH_SIZE) {
+        repays.extend(
+            client
+                .query_paginated::<ViolatorRepay>(
+                    query,
+                    "repays",
+                    json!({ "transactions": batch }),
+                    None,
+                )
+                .await?,
+        );
+    }
+    Ok(repays)
+}
+
+/// Liability vault of each (transaction, account) that repaid
+fn violator_vaults(repays: &[ViolatorRepay]) -> HashMap<(&str, &str), &str> {
+    repays
+        .iter()
+        .map(|repay| {
+            (
+                (repay.transaction_hash.as_str(), repay.account.as_str()),
+                repay.vault.as_str(),
+            )
+        })
+        .collect()
+}
+
+/// Attach the liability vault and socialized debt of each liquidation's violator
+fn correlate(
+    mut liquidations: Vec<Liquidation>,
+    repays: &[ViolatorRepay],
+    socializations: Vec<DebtSocialization>,
+) -> Vec<Liquidation> {
+    let vaults = violator_vaults(repays);
+    let mut socialized: HashMap<(String, String), DebtSocialization> = socializations
+        .into_iter()
+        .map(|s| ((s.transaction_hash.clone(), s.account.clone()), s))
+        .collect();
+
+    for liquidation in liquidations.iter_mut() {
+        let key = (
+            liquidation.transaction_hash.as_str(),
+            liquidation.violator.as_str(),
+        );
+        liquidation.vault = vaults.get(&key).map(|vault| vault.to_string());
+        liquidation.debt_socialized = socialized
+            .remove(&(key.0.to_string(), key.1.to_string()))
+            .map(|mut debt| {
+                debt.vault = liquidation.vault.clone();
+                debt
+            });
+    }
+    liquidations
+}
+
+/// Liquidations that ended in socialized bad debt, in the order given
+pub fn bad_debt_liquidations(liquidations: &[Liquidation]) -> Vec<&Liquidation> {
+    liquidations.iter().filter(|l| l.is_bad_debt()).collect()
+}
+
+/// Aggregate liquidations per liability and collateral vault pair, most liquidations first
+pub fn aggregate_by_collateral(liquidations: &[Liquidation]) -> Vec<LiquidationAggregate> {
+    aggregate_by(liquidations, |l| {
+        format!(
+            "{}/{}",
+            l.vault.as_deref().unwrap_or("unknown"),
+            l.collateral
+        )
+    })
+}
+
+/// Aggregate liquidations per liquidator, most liquidations first
+pub fn aggregate_by_liquidator(liquidations: &[Liquidation]) -> Vec<LiquidationAggregate> {
+    aggregate_by(liquidations, |l| l.liquidator.clone())
+}
+
+fn aggregate_by<F>(liquidations: &[Liquidation], key: F) -> Vec<LiquidationAggregate>
+where
+    F: Fn(&Liquidation) -> String,
+{
+    let mut aggregates: HashMap<String, LiquidationAggregate> = HashMap::new();
+    for liquidation in liquidations {
+        let key = key(liquidation);
+        let aggregate = aggregates
+            .entry(key.clone())
+            .or_insert_with(|| LiquidationAggregate {
+                key,
+                ..Default::default()
+            });
+
+        aggregate.count += 1;
+        aggregate.repay_assets += to_f64(&liquidation.repay_assets);
+        aggregate.yield_balance += to_f64(&liquidation.yield_balance);
+        aggregate.socialized_assets += liquidation.socialized_assets();
+        if liquidation.is_bad_debt() {
+            aggregate.bad_debt_count += 1;
+        }
+    }
+
+    let mut aggregates: Vec<LiquidationAggregate> = aggregates.into_values().collect();
+    aggregates.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+    aggregates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn liquidation(tx: &str, violator: &str) -> Liquidation {
+        Liquidation {
+            id: format!("{}01000000", tx),
+            liquidator: "0xliquidator".to_string(),
+            violator: violator.to_string(),
+            collateral: "0xcollateral".to_string(),
+            repay_assets: "100".to_string(),
+            yield_balance: "90".to_string(),
+            block_number: "1".to_string(),
+            block_timestamp: "1".to_string(),
+            transaction_hash: tx.to_string(),
+            vault: None,
+            debt_socialized: None,
+        }
+    }
+
+    #[test]
+    fn test_correlate_marks_socialized_bad_debt() {
+        let repays = vec![
+            ViolatorRepay {
+                account: "0xalice".to_string(),
+                vault: "0xusdc".to_string(),
+                transaction_hash: "0xtx1".to_string(),
+            },
+            ViolatorRepay {
+                account: "0xbob".to_string(),
+                vault: "0xusdc".to_string(),
+                transaction_hash: "0xtx2".to_string(),
+            },
+        ];
+        let socializations = vec![DebtSocialization {
+            id: "0xtx202000000".to_string(),
+            account: "0xbob".to_string(),
+            assets: "25".to_string(),
+            vault: None,
+            block_number: "1".to_string(),
+            block_timestamp: "1".to_string(),
+            transaction_hash: "0xtx2".to_string(),
+        }];
+
+        let liquidations = correlate(
+            vec![
+                liquidation("0xtx1", "0xalice"),
+                liquidation("0xtx2", "0xbob"),
+            ],
+            &repays,
+            socializations,
+        );
+
+        assert_eq!(liquidations[0].vault.as_deref(), Some("0xusdc"));
+        assert!(!liquidations[0].is_bad_debt());
+        let bad_debt = bad_debt_liquidations(&liquidations);
+        assert_eq!(bad_debt.len(), 1);
+        assert_eq!(
+            bad_debt[0]
+                .debt_socialized
+                .as_ref()
+                .unwrap()
+                .vault
+                .as_deref(),
+            Some("0xusdc")
+        );
+
+        let aggregates = aggregate_by_collateral(&liquidations);
+        assert_eq!(aggregates.len(), 1);
+        assert_eq!(aggregates[0].key, "0xusdc/0xcollateral");
+        assert_eq!(aggregates[0].count, 2);
+        assert_eq!(aggregates[0].bad_debt_count, 1);
+        assert_eq!(aggregates[0].socialized_assets, 25.0);
+    }
+}
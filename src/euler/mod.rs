@@ -1,11 +1,45 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Map, Value};
 
 use crate::client::GraphClient;
 
+mod borrowers;
+mod calls;
+pub mod earn;
+mod history;
+mod liquidations;
+mod portfolio;
+mod rates;
+mod registry;
 // Create a simple module for scalar types
+#[allow(dead_code)]
 mod scalars;
+mod status;
+
+pub use borrowers::{
+    fetch_borrower_universe, fetch_vault_borrowers, split_account_vault, ActiveAccount,
+};
+pub use calls::{
+    decode_selector, fetch_calls_with_context, group_batches, BatchPattern, CallBatch, CallFilter,
+    CallWithContext, EVAULT_SELECTORS,
+};
+pub use history::{fetch_vault_history, Resolution, VaultHistoryPoint};
+pub use liquidations::{
+    aggregate_by_collateral, aggregate_by_liquidator, bad_debt_liquidations,
+    fetch_debt_socializations, fetch_liquidations, DebtSocialization, DebtSocializationFilter,
+    Liquidation, LiquidationAggregate, LiquidationFilter,
+};
+pub use portfolio::{
+    fetch_owner_portfolio, sub_accounts, OwnerPortfolio, SubAccountPortfolio, VaultBalance,
+    VaultRollup, SUB_ACCOUNTS_PER_OWNER,
+};
+pub use rates::{derive_interest_fee, DEFAULT_INTEREST_FEE};
+pub use registry::{
+    fetch_asset_metadata, fetch_vault_registry, AssetMetadata, Enriched, RegisteredVault,
+    VaultMetadata, VaultRecord, VaultRegistry,
+};
+pub use status::{fetch_current_vault_states, CurrentVaultStates, VaultState};
 
 // Define simple types for responses
 pub type Decimal = String;
@@ -13,6 +47,10 @@ pub type Decimal = String;
 /// Represents an Euler vault market
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Vault {
+    /// Address of the vault. `VaultStatus` rows carry no address, so this is empty for rows
+    /// returned by [`fetch_vaults`] and set when converted from an attributed [`VaultState`].
+    #[serde(default)]
+    pub vault: String,
     pub id: String,
     #[serde(rename = "totalShares")]
     pub total_shares: String,
@@ -23,6 +61,7 @@ pub struct Vault {
     pub cash: String,
     #[serde(rename = "interestAccumulator")]
     pub interest_accumulator: String,
+    /// Borrow interest rate per second, scaled by 1e27
     #[serde(rename = "interestRate")]
     pub interest_rate: String,
     pub timestamp: String,
@@ -72,6 +111,84 @@ pub struct Withdraw {
     pub transaction_hash: String,
 }
 
+/// Represents a borrow transaction in Euler
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Borrow {
+    pub id: String,
+    pub account: String,
+    pub assets: String,
+    pub vault: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "blockTimestamp")]
+    pub block_timestamp: String,
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: String,
+}
+
+/// Represents a repay transaction in Euler
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Repay {
+    pub id: String,
+    pub account: String,
+    pub assets: String,
+    pub vault: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "blockTimestamp")]
+    pub block_timestamp: String,
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: String,
+}
+
+/// Selects which borrows or repays [`fetch_borrows`] and [`fetch_repays`] return
+#[derive(Debug, Clone, Default)]
+pub struct BorrowFilter {
+    pub vault: Option<String>,
+    pub account: Option<String>,
+    /// Inclusive lower bound on the block timestamp
+    pub from_time: Option<i64>,
+    /// Exclusive upper bound on the block timestamp
+    pub to_time: Option<i64>,
+    /// Inclusive lower bound on the block number
+    pub from_block: Option<u64>,
+    /// Exclusive upper bound on the block number
+    pub to_block: Option<u64>,
+    /// Minimum amount in native units of the vault asset
+    pub min_assets: Option<u128>,
+}
+
+impl BorrowFilter {
+    fn to_where(&self) -> Value {
+        let mut filter = Map::new();
+        if let Some(vault) = &self.vault {
+            filter.insert("vault".to_string(), json!(vault.to_lowercase()));
+        }
+        if let Some(account) = &self.account {
+            filter.insert("account".to_string(), json!(account.to_lowercase()));
+        }
+        if let Some(from_time) = self.from_time {
+            filter.insert(
+                "blockTimestamp_gte".to_string(),
+                json!(from_time.to_string()),
+            );
+        }
+        if let Some(to_time) = self.to_time {
+            filter.insert("blockTimestamp_lt".to_string(), json!(to_time.to_string()));
+        }
+        if let Some(from_block) = self.from_block {
+            filter.insert("blockNumber_gte".to_string(), json!(from_block.to_string()));
+        }
+        if let Some(to_block) = self.to_block {
+            filter.insert("blockNumber_lt".to_string(), json!(to_block.to_string()));
+        }
+        if let Some(min_assets) = self.min_assets {
+            filter.insert("assets_gte".to_string(), json!(min_assets.to_string()));
+        }
+        Value::Object(filter)
+    }
+}
+
 /// Wrapper for Vault status responses
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VaultsResponse {
@@ -91,13 +208,28 @@ pub struct WithdrawsResponse {
     pub withdraws: Vec<Withdraw>,
 }
 
+/// Wrapper for borrow transaction responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BorrowsResponse {
+    pub borrows: Vec<Borrow>,
+}
+
+/// Wrapper for repay transaction responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepaysResponse {
+    pub repays: Vec<Repay>,
+}
+
 /// Generic GraphQL response wrapper
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GraphQLResponse<T> {
     pub data: T,
 }
 
-/// Fetch vault status information from the Euler subgraph, ordered by total shares
+/// Fetch vault status information from the Euler subgraph, ordered by total shares.
+///
+/// Rows are raw `VaultStatus` events, so the same vault may appear several times; use
+/// [`fetch_current_vault_states`] for one current state per vault address.
 pub async fn fetch_vaults(client: &GraphClient, limit: i64) -> Result<VaultsResponse> {
     let query = r#"
     query EulerVaults($first: Int, $orderBy: VaultStatus_orderBy!, $orderDirection: OrderDirection!) {
@@ -180,3 +312,89 @@ pub async fn fetch_withdraws(client: &GraphClient, limit: i64) -> Result<Withdra
     let response: GraphQLResponse<WithdrawsResponse> = client.query_raw(query, variables).await?;
     Ok(response.data)
 }
+
+/// Fetch borrow transactions matching `filter` from the Euler subgraph, newest first, stopping
+/// after `limit` transactions if given. Without a limit every transaction in the filtered
+/// window is returned.
+pub async fn fetch_borrows(
+    client: &GraphClient,
+    filter: &BorrowFilter,
+    limit: Option<usize>,
+) -> Result<BorrowsResponse> {
+    let query = r#"
+    query EulerBorrows($first: Int!, $lastId: Bytes!, $orderBy: Borrow_orderBy!, $orderDirection: OrderDirection!, $where: Borrow_filter!) {
+        borrows(
+            first: $first,
+            orderBy: $orderBy,
+            orderDirection: $orderDirection,
+            where: { and: [{ id_gt: $lastId }, $where] }
+        ) {
+            id
+            account
+            assets
+            vault
+            blockNumber
+            blockTimestamp
+            transactionHash
+        }
+    }
+    "#;
+
+    let mut borrows: Vec<Borrow> = client
+        .query_newest(
+            query,
+            "borrows",
+            "blockTimestamp",
+            json!({ "where": filter.to_where() }),
+            limit,
+        )
+        .await?;
+    borrows.sort_by_key(|b| std::cmp::Reverse(b.block_timestamp.parse::<i64>().unwrap_or(0)));
+    if let Some(limit) = limit {
+        borrows.truncate(limit);
+    }
+    Ok(BorrowsResponse { borrows })
+}
+
+/// Fetch repay transactions matching `filter` from the Euler subgraph, newest first, stopping
+/// after `limit` transactions if given. Without a limit every transaction in the filtered
+/// window is returned.
+pub async fn fetch_repays(
+    client: &GraphClient,
+    filter: &BorrowFilter,
+    limit: Option<usize>,
+) -> Result<RepaysResponse> {
+    let query = r#"
+    query EulerRepays($first: Int!, $lastId: Bytes!, $orderBy: Repay_orderBy!, $orderDirection: OrderDirection!, $where: Repay_filter!) {
+        repays(
+            first: $first,
+            orderBy: $orderBy,
+            orderDirection: $orderDirection,
+            where: { and: [{ id_gt: $lastId }, $where] }
+        ) {
+            id
+            account
+            assets
+            vault
+            blockNumber
+            blockTimestamp
+            transactionHash
+        }
+    }
+    "#;
+
+    let mut repays: Vec<Repay> = client
+        .query_newest(
+            query,
+            "repays",
+            "blockTimestamp",
+            json!({ "where": filter.to_where() }),
+            limit,
+        )
+        .await?;
+    repays.sort_by_key(|r| std::cmp::Reverse(r.block_timestamp.parse::<i64>().unwrap_or(0)));
+    if let Some(limit) = limit {
+        repays.truncate(limit);
+    }
+    Ok(RepaysResponse { repays })
+}
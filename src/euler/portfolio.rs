@@ -0,0 +1,280 @@
+//! Euler positions of an owner across its EVC sub-accounts.
+//!
+//! The EVC gives every owner 256 sub-accounts, whose addresses share the owner's first 19
+//! bytes and differ in the last byte: sub-account `i` is `owner ^ i`, sub-account 0 being the
+//! owner itself. Balances and debts are tracked per sub-account and vault by
+//! `TrackingVaultBalance`.
+
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::client::GraphClient;
+use crate::numeric::to_f64;
+
+/// Number of sub-accounts the EVC assigns to each owner
+pub const SUB_ACCOUNTS_PER_OWNER: usize = 256;
+
+/// Balance and debt of a sub-account in one vault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultBalance {
+    /// Sub-account address followed by the vault address
+    pub id: String,
+    pub vault: String,
+    pub account: String,
+    #[serde(rename = "mainAddress")]
+    pub main_address: String,
+    /// Vault share balance, in native units
+    pub balance: String,
+    /// Debt, in native units of the vault asset
+    pub debt: String,
+    /// Whether the vault is the sub-account's controller, i.e. the vault it can borrow from
+    #[serde(rename = "isControllerEnabled")]
+    pub is_controller_enabled: bool,
+}
+
+/// Positions of one sub-account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubAccountPortfolio {
+    pub address: String,
+    /// Position of the sub-account among the owner's 256, 0 being the owner address
+    pub index: u8,
+    /// Whether the subgraph has seen the sub-account as an EVC `Account`
+    pub registered: bool,
+    /// Vault enabled as controller, if any
+    pub controller: Option<String>,
+    pub positions: Vec<VaultBalance>,
+}
+
+/// Balances and debts of an owner in one vault, summed over its sub-accounts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VaultRollup {
+    pub vault: String,
+    /// Vault share balance, in native units
+    pub balance: f64,
+    /// Debt, in native units of the vault asset
+    pub debt: f64,
+    /// Number of sub-accounts with a position in the vault
+    pub sub_accounts: usize,
+    /// Number of sub-accounts that have the vault enabled as controller
+    pub controllers: usize,
+}
+
+/// Euler positions of an owner per sub-account, with a per-vault rollup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OwnerPortfolio {
+    pub owner: String,
+    /// Sub-accounts with a position or controller, by index
+    pub sub_accounts: Vec<SubAccountPortfolio>,
+    /// Ordered by vault address
+    pub vaults: Vec<VaultRollup>,
+}
+
+impl OwnerPortfolio {
+    /// Sub-accounts that have a controller enabled and may therefore hold debt
+    pub fn borrowing_sub_accounts(&self) -> impl Iterator<Item = &SubAccountPortfolio> {
+        self.sub_accounts.iter().filter(|s| s.controller.is_some())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RegisteredAccount {
+    #[serde(rename = "subAccount")]
+    sub_account: String,
+}
+
+/// Addresses of the 256 EVC sub-accounts of `owner`, by index
+pub fn sub_accounts(owner: &str) -> Result<Vec<String>> {
+    let owner = owner.trim_start_matches("0x").to_lowercase();
+    if owner.len() != 40 || !owner.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("Invalid owner address '{}'", owner));
+    }
+    let prefix = &owner[..38];
+    let last = u8::from_str_radix(&owner[38..], 16)?;
+    Ok((0..SUB_ACCOUNTS_PER_OWNER)
+        .map(|index| format!("0x{}{:02x}", prefix, last ^ index as u8))
+        .collect())
+}
+
+/// Fetch the balances, debts and controllers of all sub-accounts of `owner`
+pub async fn fetch_owner_portfolio(client: &GraphClient, owner: &str) -> Result<OwnerPortfolio> {
+    let balances_query = r#"
+    query EulerSubAccountBalances($first: Int!, $lastId: Bytes!, $accounts: [Bytes!]!) {
+        trackingVaultBalances(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, account_in: $accounts }
+        ) {
+            id
+            vault
+            account
+            mainAddress
+            balance
+            debt
+            isControllerEnabled
+        }
+    }
+    "#;
+    let accounts_query = r#"
+    query EulerSubAccounts($first: Int!, $lastId: Bytes!, $accounts: [Bytes!]!) {
+        accounts(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, subAccount_in: $accounts }
+        ) {
+            id
+            subAccount
+        }
+    }
+    "#;
+
+    let addresses = sub_accounts(owner)?;
+    let variables = json!({ "accounts": addresses });
+    let balances: Vec<VaultBalance> = client
+        .query_paginated(
+            balances_query,
+            "trackingVaultBalances",
+            variables.clone(),
+            None,
+        )
+        .await?;
+    let registered: Vec<RegisteredAccount> = client
+        .query_paginated(accounts_query, "accounts", variables, None)
+        .await?;
+    let registered: HashSet<String> = registered
+        .into_iter()
+        .map(|account| account.sub_account.to_lowercase())
+        .collect();
+
+    Ok(build_portfolio(
+        addresses[0].clone(),
+        &addresses,
+        &registered,
+        balances,
+    ))
+}
+
+fn build_portfolio(
+    owner: String,
+    addresses: &[String],
+    registered: &HashSet<String>,
+    balances: Vec<VaultBalance>,
+) -> OwnerPortfolio {
+    let mut by_account: BTreeMap<usize, Vec<VaultBalance>> = BTreeMap::new();
+    let mut vaults: BTreeMap<String, VaultRollup> = BTreeMap::new();
+    for balance in balances {
+        let (balance_amount, debt) = (to_f64(&balance.balance), to_f64(&balance.debt));
+        if balance_amount == 0.0 && debt == 0.0 && !balance.is_controller_enabled {
+            continue;
+        }
+        let account = balance.account.to_lowercase();
+        let Some(index) = addresses.iter().position(|a| *a == account) else {
+            continue;
+        };
+
+        let rollup = vaults
+            .entry(balance.vault.clone())
+            .or_insert_with(|| VaultRollup {
+                vault: balance.vault.clone(),
+                ..Default::default()
+            });
+        rollup.balance += balance_amount;
+        rollup.debt += debt;
+        rollup.sub_accounts += 1;
+        if balance.is_controller_enabled {
+            rollup.controllers += 1;
+        }
+        by_account.entry(index).or_default().push(balance);
+    }
+
+    let sub_accounts = by_account
+        .into_iter()
+        .map(|(index, mut positions)| {
+            positions.sort_by(|a, b| a.vault.cmp(&b.vault));
+            let address = addresses[index].clone();
+            SubAccountPortfolio {
+                registered: registered.contains(&address),
+                controller: positions
+                    .iter()
+                    .find(|p| p.is_controller_enabled)
+                    .map(|p| p.vault.clone()),
+                index: index as u8,
+                address,
+                positions,
+            }
+        })
+        .collect();
+
+    OwnerPortfolio {
+        owner,
+        sub_accounts,
+        vaults: vaults.into_values().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balance(
+        account: &str,
+        vault: &str,
+        balance: &str,
+        debt: &str,
+        controller: bool,
+    ) -> VaultBalance {
+        VaultBalance {
+            id: format!("{}{}", account, vault.trim_start_matches("0x")),
+            vault: vault.to_string(),
+            account: account.to_string(),
+            main_address: account.to_string(),
+            balance: balance.to_string(),
+            debt: debt.to_string(),
+            is_controller_enabled: controller,
+        }
+    }
+
+    #[test]
+    fn test_sub_accounts_flip_last_byte() {
+        let owner = "0xAbCdEf0000000000000000000000000000000003";
+        let accounts = sub_accounts(owner).unwrap();
+
+        assert_eq!(accounts.len(), 256);
+        assert_eq!(accounts[0], owner.to_lowercase());
+        assert_eq!(accounts[1], "0xabcdef0000000000000000000000000000000002");
+        assert_eq!(accounts[255], "0xabcdef00000000000000000000000000000000fc");
+        assert!(sub_accounts("0x1234").is_err());
+    }
+
+    #[test]
+    fn test_portfolio_rolls_up_sub_accounts_per_vault() {
+        let addresses = sub_accounts("0x0000000000000000000000000000000000000010").unwrap();
+        let balances = vec![
+            balance(&addresses[0], "0xusdc", "100", "0", false),
+            balance(&addresses[3], "0xusdc", "50", "0", false),
+            balance(&addresses[3], "0xweth", "0", "7", true),
+            balance(&addresses[5], "0xweth", "0", "0", false),
+        ];
+        let registered = HashSet::from([addresses[3].clone()]);
+
+        let portfolio = build_portfolio(addresses[0].clone(), &addresses, &registered, balances);
+
+        assert_eq!(portfolio.sub_accounts.len(), 2);
+        let borrower = &portfolio.sub_accounts[1];
+        assert_eq!(borrower.index, 3);
+        assert!(borrower.registered);
+        assert_eq!(borrower.controller.as_deref(), Some("0xweth"));
+        assert_eq!(portfolio.borrowing_sub_accounts().count(), 1);
+
+        assert_eq!(portfolio.vaults.len(), 2);
+        assert_eq!(portfolio.vaults[0].vault, "0xusdc");
+        assert_eq!(portfolio.vaults[0].balance, 150.0);
+        assert_eq!(portfolio.vaults[0].sub_accounts, 2);
+        assert_eq!(portfolio.vaults[1].debt, 7.0);
+        assert_eq!(portfolio.vaults[1].controllers, 1);
+    }
+}
@@ -0,0 +1,159 @@
+//! Borrow and supply rates of Euler vaults, following the EVK interest model.
+//!
+//! The EVK stores the borrow `interestRate` as a per-second rate scaled by 1e27 and compounds
+//! it every second, so
+//!
+//! - borrow APY = `(1 + interestRate / 1e27) ^ seconds per year - 1`
+//! - utilization = `totalBorrows / (cash + totalBorrows)`
+//! - supply APY = `borrow APY * utilization * (1 - interest fee)`
+//!
+//! The interest fee is the share of borrow interest kept by the governor and protocol. The EVK
+//! mints it to the vault as `accumulatedFees` shares on every interest accrual, until
+//! `ConvertFee` transfers them out and resets `accumulatedFees` to zero. The fee of a vault can
+//! therefore be derived from two states with [`derive_interest_fee`]; otherwise
+//! [`DEFAULT_INTEREST_FEE`] is a reasonable assumption.
+
+use super::{Vault, VaultState};
+use crate::numeric::{ratio, to_f64};
+
+const SECONDS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0;
+
+/// Scale of the EVK per-second interest rate and interest accumulator
+const RAY: f64 = 1e27;
+
+/// Default EVK interest fee of 10%
+pub const DEFAULT_INTEREST_FEE: f64 = 0.1;
+
+fn borrow_apy(interest_rate: &str) -> f64 {
+    ((to_f64(interest_rate) / RAY).ln_1p() * SECONDS_PER_YEAR).exp_m1()
+}
+
+fn utilization(cash: &str, total_borrows: &str) -> f64 {
+    let borrows = to_f64(total_borrows);
+    ratio(borrows, to_f64(cash) + borrows)
+}
+
+impl VaultState {
+    /// Cash plus borrows, in native units of the vault asset
+    pub fn total_assets(&self) -> f64 {
+        to_f64(&self.cash) + to_f64(&self.total_borrows)
+    }
+
+    /// Borrows divided by total assets, between 0 and 1
+    pub fn utilization(&self) -> f64 {
+        utilization(&self.cash, &self.total_borrows)
+    }
+
+    /// Borrow APY as a fraction, e.g. 0.05 for 5%
+    pub fn borrow_apy(&self) -> f64 {
+        borrow_apy(&self.interest_rate)
+    }
+
+    /// Supply APY as a fraction, given the share of interest taken as fees between 0 and 1
+    pub fn supply_apy(&self, interest_fee: f64) -> f64 {
+        self.borrow_apy() * self.utilization() * (1.0 - interest_fee)
+    }
+}
+
+impl Vault {
+    /// Borrows divided by total assets, between 0 and 1
+    pub fn utilization(&self) -> f64 {
+        utilization(&self.cash, &self.total_borrows)
+    }
+
+    /// Borrow APY as a fraction, e.g. 0.05 for 5%
+    pub fn borrow_apy(&self) -> f64 {
+        borrow_apy(&self.interest_rate)
+    }
+
+    /// Supply APY as a fraction, given the share of interest taken as fees between 0 and 1
+    pub fn supply_apy(&self, interest_fee: f64) -> f64 {
+        self.borrow_apy() * self.utilization() * (1.0 - interest_fee)
+    }
+}
+
+/// Interest fee of a vault between two of its states, `before` being the earlier one.
+///
+/// Interest accrued over the period is `totalBorrows * (accumulator1 / accumulator0 - 1)` and
+/// the fee is the value of the fee shares minted meanwhile. Returns `None` when no interest
+/// accrued or fees were converted in between.
+pub fn derive_interest_fee(before: &VaultState, after: &VaultState) -> Option<f64> {
+    let accumulator = to_f64(&before.interest_accumulator);
+    let minted = to_f64(&after.accumulated_fees) - to_f64(&before.accumulated_fees);
+    if accumulator <= 0.0 || minted < 0.0 {
+        return None;
+    }
+
+    let interest =
+        to_f64(&before.total_borrows) * (to_f64(&after.interest_accumulator) / accumulator - 1.0);
+    let total_shares = to_f64(&after.total_shares);
+    if interest <= 0.0 || total_shares <= 0.0 {
+        return None;
+    }
+
+    let fee_assets = minted * after.total_assets() / total_shares;
+    Some((fee_assets / interest).clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(
+        cash: f64,
+        total_borrows: f64,
+        total_shares: f64,
+        accumulated_fees: f64,
+        accumulator: f64,
+        interest_rate: f64,
+    ) -> VaultState {
+        VaultState {
+            vault: "0xvault".to_string(),
+            id: "0x01".to_string(),
+            total_shares: total_shares.to_string(),
+            total_borrows: total_borrows.to_string(),
+            accumulated_fees: accumulated_fees.to_string(),
+            cash: cash.to_string(),
+            interest_accumulator: format!("{:.0}", accumulator),
+            interest_rate: format!("{:.0}", interest_rate),
+            timestamp: "0".to_string(),
+            block_number: "0".to_string(),
+            block_timestamp: "0".to_string(),
+            transaction_hash: "0xtx".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_rates_follow_evk_model() {
+        // Per-second rate that compounds to 5% over a year
+        let rate = (1.05f64.powf(1.0 / SECONDS_PER_YEAR) - 1.0) * RAY;
+        let vault = state(250.0, 750.0, 1000.0, 0.0, RAY, rate);
+
+        assert!((vault.borrow_apy() - 0.05).abs() < 1e-9);
+        assert_eq!(vault.utilization(), 0.75);
+        assert!((vault.supply_apy(DEFAULT_INTEREST_FEE) - 0.05 * 0.75 * 0.9).abs() < 1e-9);
+        assert_eq!(state(0.0, 0.0, 0.0, 0.0, RAY, rate).supply_apy(0.1), 0.0);
+    }
+
+    #[test]
+    fn test_derive_interest_fee_from_accumulated_fees() {
+        // 10% interest accrues on 500 borrowed, a fifth of it as fees
+        let before = state(500.0, 500.0, 1000.0, 0.0, RAY, 0.0);
+        let total_shares = 1050.0 * 1000.0 / (1050.0 - 10.0);
+        let after = state(
+            500.0,
+            550.0,
+            total_shares,
+            total_shares - 1000.0,
+            1.1 * RAY,
+            0.0,
+        );
+
+        let fee = derive_interest_fee(&before, &after).unwrap();
+        assert!((fee - 0.2).abs() < 1e-9);
+
+        // Fees converted in between
+        let converted = state(500.0, 560.0, 1000.0, 0.0, 1.12 * RAY, 0.0);
+        assert!(derive_interest_fee(&after, &converted).is_none());
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{Borrow, Deposit, GraphQLResponse, Repay, Token, Vault, Withdraw};
+use crate::client::{GraphClient, MAX_PAGE_SIZE};
+use crate::numeric::to_f64;
+
+/// An EVault as recorded by its `EVaultCreated` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisteredVault {
+    pub id: String,
+    /// Address of the vault
+    pub evault: String,
+    pub creator: String,
+    /// Address of the underlying asset
+    pub asset: String,
+    /// Address of the vault's debt token
+    #[serde(rename = "dToken")]
+    pub d_token: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "blockTimestamp")]
+    pub block_timestamp: String,
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: String,
+}
+
+/// Human readable description of a vault and its asset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultMetadata {
+    pub vault: String,
+    pub asset: String,
+    pub asset_symbol: Option<String>,
+    pub asset_name: Option<String>,
+    pub asset_decimals: Option<i32>,
+    pub d_token: String,
+    pub creator: String,
+}
+
+impl VaultMetadata {
+    /// Convert a native asset amount into whole units, if the asset decimals are known
+    pub fn amount(&self, raw: &str) -> Option<f64> {
+        self.asset_decimals
+            .map(|decimals| to_f64(raw) / 10f64.powi(decimals))
+    }
+}
+
+/// An Euler record that belongs to a single vault
+pub trait VaultRecord {
+    fn vault_address(&self) -> &str;
+}
+
+/// Empty, and so never enriched, for raw `VaultStatus` rows, which carry no vault address
+impl VaultRecord for Vault {
+    fn vault_address(&self) -> &str {
+        &self.vault
+    }
+}
+
+impl VaultRecord for Deposit {
+    fn vault_address(&self) -> &str {
+        &self.vault
+    }
+}
+
+impl VaultRecord for Withdraw {
+    fn vault_address(&self) -> &str {
+        &self.vault
+    }
+}
+
+impl VaultRecord for Borrow {
+    fn vault_address(&self) -> &str {
+        &self.vault
+    }
+}
+
+impl VaultRecord for Repay {
+    fn vault_address(&self) -> &str {
+        &self.vault
+    }
+}
+
+/// A record together with the metadata of its vault, when the vault is registered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Enriched<T> {
+    #[serde(flatten)]
+    pub record: T,
+    pub metadata: Option<VaultMetadata>,
+}
+
+/// All known EVaults keyed by address, with optional asset metadata
+#[derive(Debug, Clone, Default)]
+pub struct VaultRegistry {
+    vaults: Vec<RegisteredVault>,
+    by_address: HashMap<String, usize>,
+    assets: HashMap<String, Token>,
+}
+
+impl VaultRegistry {
+    pub fn new(vaults: Vec<RegisteredVault>) -> Self {
+        let by_address = vaults
+            .iter()
+            .enumerate()
+            .map(|(index, vault)| (vault.evault.to_lowercase(), index))
+            .collect();
+        VaultRegistry {
+            vaults,
+            by_address,
+            assets: HashMap::new(),
+        }
+    }
+
+    /// Registered vaults, oldest first
+    pub fn vaults(&self) -> &[RegisteredVault] {
+        &self.vaults
+    }
+
+    pub fn get(&self, vault: &str) -> Option<&RegisteredVault> {
+        self.by_address
+            .get(&vault.to_lowercase())
+            .map(|&index| &self.vaults[index])
+    }
+
+    /// Distinct underlying asset addresses of all registered vaults
+    pub fn asset_addresses(&self) -> Vec<String> {
+        let mut assets: Vec<String> = self.vaults.iter().map(|v| v.asset.to_lowercase()).collect();
+        assets.sort();
+        assets.dedup();
+        assets
+    }
+
+    /// Register symbol, name and decimals of an asset, keyed by `token.id`
+    pub fn insert_asset(&mut self, token: Token) {
+        self.assets.insert(token.id.to_lowercase(), token);
+    }
+
+    pub fn metadata(&self, vault: &str) -> Option<VaultMetadata> {
+        let registered = self.get(vault)?;
+        let token = self.assets.get(&registered.asset.to_lowercase());
+        Some(VaultMetadata {
+            vault: registered.evault.clone(),
+            asset: registered.asset.clone(),
+            asset_symbol: token.map(|t| t.symbol.clone()),
+            asset_name: token.map(|t| t.name.clone()),
+            asset_decimals: token.map(|t| t.decimals),
+            d_token: registered.d_token.clone(),
+            creator: registered.creator.clone(),
+        })
+    }
+
+    /// Attach vault metadata to each record
+    pub fn enrich<T: VaultRecord>(&self, records: Vec<T>) -> Vec<Enriched<T>> {
+        records
+            .into_iter()
+            .map(|record| Enriched {
+                metadata: self.metadata(record.vault_address()),
+                record,
+            })
+            .collect()
+    }
+}
+
+/// Fetch every EVault created through the factory, oldest first
+pub async fn fetch_vault_registry(client: &GraphClient) -> Result<VaultRegistry> {
+    let query = r#"
+    query EulerVaultRegistry($first: Int!, $lastId: Bytes!) {
+        eVaultCreateds(first: $first, orderBy: id, orderDirection: asc, where: { id_gt: $lastId }) {
+            id
+            evault
+            creator
+            asset
+            dToken
+            blockNumber
+            blockTimestamp
+            transactionHash
+        }
+    }
+    "#;
+
+    let mut vaults: Vec<RegisteredVault> = client
+        .query_paginated(query, "eVaultCreateds", json!({}), None)
+        .await?;
+    vaults.sort_by_key(|vault| vault.block_number.parse::<u64>().unwrap_or(0));
+    Ok(VaultRegistry::new(vaults))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TokensResponse {
+    tokens: Vec<Token>,
+}
+
+/// Asset metadata found by [`fetch_asset_metadata`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AssetMetadata {
+    pub tokens: Vec<Token>,
+    /// Requested assets without a `Token` entity, lowercased. Every asset is unresolved when
+    /// the subgraph indexes another chain than the Euler deployment.
+    pub unresolved: Vec<String>,
+}
+
+/// Fetch symbol, name and decimals of `assets` from a subgraph that indexes `Token` entities
+/// keyed by address. The crate configures no such source for the Euler chain, so the caller
+/// supplies `client`; the Morpho Base subgraph only fits a deployment on Base. Nothing checks
+/// that `client` points at the same chain as the Euler deployment, so it must: a token at the
+/// same address on another chain would be attached to the wrong vault. Assets without a
+/// `Token` are returned as unresolved rather than dropped.
+pub async fn fetch_asset_metadata(
+    client: &GraphClient,
+    assets: &[String],
+) -> Result<AssetMetadata> {
+    let query = r#"
+    query AssetMetadata($ids: [Bytes!]!, $first: Int) {
+        tokens(first: $first, where: { id_in: $ids }) {
+            id
+            symbol
+            name
+            decimals
+        }
+    }
+    "#;
+
+    let ids: Vec<String> = assets.iter().map(|asset| asset.to_lowercase()).collect();
+    let mut tokens = Vec::new();
+    for chunk in ids.chunks(MAX_PAGE_SIZE) {
+        let variables = json!({
+            "ids": chunk,
+            "first": chunk.len(),
+        });
+        let response: GraphQLResponse<TokensResponse> = client.query_raw(query, variables).await?;
+        tokens.extend(response.data.tokens);
+    }
+    Ok(resolve_assets(ids, tokens))
+}
+
+fn resolve_assets(mut ids: Vec<String>, tokens: Vec<Token>) -> AssetMetadata {
+    ids.sort();
+    ids.dedup();
+    let unresolved = ids
+        .into_iter()
+        .filter(|id| !tokens.iter().any(|token| token.id.to_lowercase() == *id))
+        .collect();
+    AssetMetadata { tokens, unresolved }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enrich_deposits_with_asset_metadata() {
+        let mut registry = VaultRegistry::new(vec![RegisteredVault {
+            id: "0x01".to_string(),
+            evault: "0xVault".to_string(),
+            creator: "0xcreator".to_string(),
+            asset: "0xUSDC".to_string(),
+            d_token: "0xdtoken".to_string(),
+            block_number: "1".to_string(),
+            block_timestamp: "1".to_string(),
+            transaction_hash: "0xhash".to_string(),
+        }]);
+        registry.insert_asset(Token {
+            id: "0xusdc".to_string(),
+            symbol: "USDC".to_string(),
+            name: "USD Coin".to_string(),
+            decimals: 6,
+        });
+
+        let deposit = |vault: &str| Deposit {
+            id: "0x02".to_string(),
+            sender: "0xsender".to_string(),
+            owner: "0xowner".to_string(),
+            assets: "2500000".to_string(),
+            shares: "2500000".to_string(),
+            vault: vault.to_string(),
+            block_number: "2".to_string(),
+            block_timestamp: "2".to_string(),
+            transaction_hash: "0xhash".to_string(),
+        };
+
+        let enriched = registry.enrich(vec![deposit("0xvault"), deposit("0xunknown")]);
+        let metadata = enriched[0].metadata.as_ref().unwrap();
+        assert_eq!(metadata.asset_symbol.as_deref(), Some("USDC"));
+        assert_eq!(metadata.amount(&enriched[0].record.assets), Some(2.5));
+        assert!(enriched[1].metadata.is_none());
+
+        let lookup = resolve_assets(
+            vec!["0xusdc".to_string(), "0xweth".to_string()],
+            vec![Token {
+                id: "0xUSDC".to_string(),
+                symbol: "USDC".to_string(),
+                name: "USD Coin".to_string(),
+                decimals: 6,
+            }],
+        );
+        assert_eq!(lookup.unresolved, vec!["0xweth".to_string()]);
+    }
+}
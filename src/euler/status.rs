@@ -0,0 +1,470 @@
+//! Euler vault state attributed to vault addresses.
+//!
+//! `VaultStatus` rows are immutable and carry no vault address, only the transaction that
+//! emitted them. Every EVK operation goes through the EVC, which records a `CallWithContext`
+//! per transaction and vault, so a status row is attributed to a vault as follows:
+//!
+//! - the transaction touched a single vault: the row belongs to it
+//! - the transaction touched several vaults: status checks run in the order vaults first
+//!   requested them, so rows are matched in log order with vaults ordered by their first
+//!   `Deposit`, `Withdraw`, `Borrow`, `Repay` or `Transfer` event in the transaction
+//!
+//! Rows that cannot be attributed either way are skipped.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{Vault, VaultRecord};
+use crate::client::GraphClient;
+
+/// Number of transaction hashes sent per `transactionHash_in` lookup
+const TRANSACTION_BATCH_SIZE: usize = 500;
+
+/// Number of vaults queried per request when looking up their latest transactions
+const VAULT_BATCH_SIZE: usize = 50;
+
+/// Latest transactions inspected per vault, in case the newest ones cannot be attributed
+const TRANSACTIONS_PER_VAULT: usize = 5;
+
+/// Vault-level events used to order vaults within a transaction
+const VAULT_EVENT_COLLECTIONS: [&str; 5] =
+    ["deposits", "withdraws", "borrows", "repays", "transfers"];
+
+/// A `VaultStatus` row attributed to the vault that emitted it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultState {
+    /// Address of the vault
+    #[serde(default)]
+    pub vault: String,
+    /// Id of the `VaultStatus` row
+    pub id: String,
+    #[serde(rename = "totalShares")]
+    pub total_shares: String,
+    #[serde(rename = "totalBorrows")]
+    pub total_borrows: String,
+    #[serde(rename = "accumulatedFees")]
+    pub accumulated_fees: String,
+    pub cash: String,
+    #[serde(rename = "interestAccumulator")]
+    pub interest_accumulator: String,
+    /// Borrow interest rate per second, scaled by 1e27
+    #[serde(rename = "interestRate")]
+    pub interest_rate: String,
+    pub timestamp: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    #[serde(rename = "blockTimestamp")]
+    pub block_timestamp: String,
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: String,
+}
+
+impl VaultState {
+    pub fn block_timestamp(&self) -> i64 {
+        self.block_timestamp.parse().unwrap_or(0)
+    }
+
+    pub(crate) fn log_index(&self) -> i32 {
+        log_index(&self.id)
+    }
+}
+
+impl VaultRecord for VaultState {
+    fn vault_address(&self) -> &str {
+        &self.vault
+    }
+}
+
+impl From<VaultState> for Vault {
+    fn from(state: VaultState) -> Self {
+        Vault {
+            vault: state.vault,
+            id: state.id,
+            total_shares: state.total_shares,
+            total_borrows: state.total_borrows,
+            accumulated_fees: state.accumulated_fees,
+            cash: state.cash,
+            interest_accumulator: state.interest_accumulator,
+            interest_rate: state.interest_rate,
+            timestamp: state.timestamp,
+        }
+    }
+}
+
+/// Log index encoded in an event entity id, which is the transaction hash followed by the
+/// little-endian log index
+pub(crate) fn log_index(id: &str) -> i32 {
+    let hex = id.trim_start_matches("0x");
+    if hex.len() < 8 {
+        return 0;
+    }
+    let mut bytes = [0u8; 4];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        let start = hex.len() - 8 + i * 2;
+        *byte = u8::from_str_radix(&hex[start..start + 2], 16).unwrap_or(0);
+    }
+    i32::from_le_bytes(bytes)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct VaultCall {
+    vault: String,
+    #[serde(rename = "transactionHash")]
+    transaction_hash: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct VaultEvent {
+    id: String,
+    vault: String,
+    #[serde(rename = "transactionHash")]
+    transaction_hash: String,
+}
+
+/// Latest states found by [`fetch_current_vault_states`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CurrentVaultStates {
+    /// In the order the vaults were first requested, one per distinct vault
+    pub states: Vec<VaultState>,
+    /// Requested vaults, lowercased, whose latest transactions yielded no attributable status
+    pub unresolved: Vec<String>,
+}
+
+/// Fetch the latest state of each of `vaults`, e.g. the addresses of
+/// [`VaultRegistry::vaults`](super::VaultRegistry::vaults). Vaults without an attributable
+/// status among their latest transactions are returned as unresolved.
+pub async fn fetch_current_vault_states(
+    client: &GraphClient,
+    vaults: &[String],
+) -> Result<CurrentVaultStates> {
+    let mut requested = HashSet::new();
+    let vaults: Vec<String> = vaults
+        .iter()
+        .map(|v| v.to_lowercase())
+        .filter(|v| requested.insert(v.clone()))
+        .collect();
+
+    let mut transactions = Vec::new();
+    for batch in vaults.chunks(VAULT_BATCH_SIZE) {
+        transactions.extend(fetch_latest_transactions(client, batch).await?);
+    }
+    transactions.sort();
+    transactions.dedup();
+
+    let mut latest: HashMap<String, VaultState> = HashMap::new();
+    for state in fetch_states_for_transactions(client, &transactions).await? {
+        let newer = latest.get(&state.vault).is_none_or(|current| {
+            (state.block_timestamp(), state.log_index())
+                > (current.block_timestamp(), current.log_index())
+        });
+        if newer {
+            latest.insert(state.vault.clone(), state);
+        }
+    }
+
+    let mut current = CurrentVaultStates::default();
+    for vault in vaults {
+        match latest.remove(&vault) {
+            Some(state) => current.states.push(state),
+            None => current.unresolved.push(vault),
+        }
+    }
+    if !current.unresolved.is_empty() {
+        debug!(
+            "No attributable status for {} of {} vaults",
+            current.unresolved.len(),
+            current.states.len() + current.unresolved.len()
+        );
+    }
+    Ok(current)
+}
+
+/// Hashes of the latest transactions that went through the EVC for each vault
+async fn fetch_latest_transactions(client: &GraphClient, vaults: &[String]) -> Result<Vec<String>> {
+    let declarations: Vec<String> = (0..vaults.len())
+        .map(|i| format!("$vault{}: Bytes!", i))
+        .collect();
+    let selections: Vec<String> = (0..vaults.len())
+        .map(|i| {
+            format!(
+                "vault{i}: callWithContexts(first: {first}, orderBy: blockNumber, \
+                 orderDirection: desc, where: {{ vault: $vault{i} }}) {{ transactionHash }}",
+                i = i,
+                first = TRANSACTIONS_PER_VAULT,
+            )
+        })
+        .collect();
+    let query = format!(
+        "query EulerLatestVaultTransactions({}) {{ {} }}",
+        declarations.join(", "),
+        selections.join(" ")
+    );
+
+    let mut variables = json!({});
+    for (i, vault) in vaults.iter().enumerate() {
+        variables[format!("vault{}", i)] = json!(vault);
+    }
+
+    let data: Value = client.query_raw(&query, variables).await?;
+    let mut transactions = Vec::new();
+    for i in 0..vaults.len() {
+        let calls = data
+            .get(format!("vault{}", i))
+            .and_then(Value::as_array)
+            .ok_or_else(|| anyhow!("Missing 'vault{}' in response", i))?;
+        transactions.extend(
+            calls
+                .iter()
+                .filter_map(|call| call.get("transactionHash")?.as_str())
+                .map(str::to_string),
+        );
+    }
+    Ok(transactions)
+}
+
+/// Fetch every `VaultStatus` emitted in `transactions` and attribute it to its vault
+pub(crate) async fn fetch_states_for_transactions(
+    client: &GraphClient,
+    transactions: &[String],
+) -> Result<Vec<VaultState>> {
+    let statuses_query = r#"
+    query EulerVaultStatusesByTransaction($first: Int!, $lastId: Bytes!, $transactions: [Bytes!]!) {
+        vaultStatuses(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, transactionHash_in: $transactions }
+        ) {
+            id
+            totalShares
+            totalBorrows
+            accumulatedFees
+            cash
+            interestAccumulator
+            interestRate
+            timestamp
+            blockNumber
+            blockTimestamp
+            transactionHash
+        }
+    }
+    "#;
+    let calls_query = r#"
+    query EulerVaultCallsByTransaction($first: Int!, $lastId: Bytes!, $transactions: [Bytes!]!) {
+        callWithContexts(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, transactionHash_in: $transactions }
+        ) {
+            id
+            vault
+            transactionHash
+        }
+    }
+    "#;
+
+    let mut states = Vec::new();
+    for batch in transactions.chunks(TRANSACTION_BATCH_SIZE) {
+        let variables = json!({ "transactions": batch });
+        let statuses: Vec<VaultState> = client
+            .query_paginated(statuses_query, "vaultStatuses", variables.clone(), None)
+            .await?;
+        let calls: Vec<VaultCall> = client
+            .query_paginated(calls_query, "callWithContexts", variables, None)
+            .await?;
+
+        let mut by_transaction: HashMap<String, Vec<VaultState>> = HashMap::new();
+        for status in statuses {
+            by_transaction
+                .entry(status.transaction_hash.clone())
+                .or_default()
+                .push(status);
+        }
+        let mut vaults_by_transaction: HashMap<String, Vec<String>> = HashMap::new();
+        for call in calls {
+            let vaults = vaults_by_transaction
+                .entry(call.transaction_hash)
+                .or_default();
+            if !vaults.contains(&call.vault) {
+                vaults.push(call.vault);
+            }
+        }
+
+        let ambiguous: Vec<&str> = by_transaction
+            .keys()
+            .filter(|tx| vaults_by_transaction.get(*tx).map_or(0, Vec::len) > 1)
+            .map(String::as_str)
+            .collect();
+        let events = fetch_vault_events(client, &ambiguous).await?;
+
+        for (transaction, statuses) in by_transaction {
+            let vaults = vaults_by_transaction
+                .get(&transaction)
+                .cloned()
+                .unwrap_or_default();
+            let tx_events: Vec<&VaultEvent> = events
+                .iter()
+                .filter(|event| event.transaction_hash == transaction)
+                .collect();
+            states.extend(attribute(statuses, &vaults, &tx_events));
+        }
+    }
+
+    Ok(states)
+}
+
+async fn fetch_vault_events(
+    client: &GraphClient,
+    transactions: &[&str],
+) -> Result<Vec<VaultEvent>> {
+    let mut events = Vec::new();
+    if transactions.is_empty() {
+        return Ok(events);
+    }
+
+    for collection in VAULT_EVENT_COLLECTIONS {
+        let query = format!(
+            r#"
+    query EulerVaultEventsByTransaction($first: Int!, $lastId: Bytes!, $transactions: [Bytes!]!) {{
+        {}(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: {{ id_gt: $lastId, transactionHash_in: $transactions }}
+        ) {{
+            id
+            vault
+            transactionHash
+        }}
+    }}
+    "#,
+            collection
+        );
+        events.extend(
+            client
+                .query_paginated::<VaultEvent>(
+                    &query,
+                    collection,
+                    json!({ "transactions": transactions }),
+                    None,
+                )
+                .await?,
+        );
+    }
+    Ok(events)
+}
+
+/// Attribute the status rows of one transaction to the vaults it touched
+fn attribute(
+    mut statuses: Vec<VaultState>,
+    vaults: &[String],
+    events: &[&VaultEvent],
+) -> Vec<VaultState> {
+    if vaults.len() == 1 && statuses.len() == 1 {
+        statuses[0].vault = vaults[0].clone();
+        return statuses;
+    }
+
+    let mut ordered: Vec<&VaultEvent> = events.to_vec();
+    ordered.sort_by_key(|event| log_index(&event.id));
+    let mut order: Vec<String> = Vec::new();
+    for event in ordered {
+        if !order.contains(&event.vault) {
+            order.push(event.vault.clone());
+        }
+    }
+
+    let complete = vaults.iter().all(|vault| order.contains(vault));
+    if !complete || order.len() != statuses.len() {
+        debug!(
+            "Skipping {} unattributable vault statuses in transaction {}",
+            statuses.len(),
+            statuses
+                .first()
+                .map(|s| s.transaction_hash.as_str())
+                .unwrap_or_default()
+        );
+        return Vec::new();
+    }
+
+    statuses.sort_by_key(VaultState::log_index);
+    statuses
+        .into_iter()
+        .zip(order)
+        .map(|(mut status, vault)| {
+            status.vault = vault;
+            status
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(log_index: i32) -> String {
+        let bytes = log_index.to_le_bytes();
+        format!(
+            "0x{}{}",
+            "ab".repeat(32),
+            bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        )
+    }
+
+    fn status(log_index: i32) -> VaultState {
+        VaultState {
+            vault: String::new(),
+            id: id(log_index),
+            total_shares: "0".to_string(),
+            total_borrows: "0".to_string(),
+            accumulated_fees: "0".to_string(),
+            cash: "0".to_string(),
+            interest_accumulator: "0".to_string(),
+            interest_rate: "0".to_string(),
+            timestamp: "0".to_string(),
+            block_number: "0".to_string(),
+            block_timestamp: "0".to_string(),
+            transaction_hash: "0xtx".to_string(),
+        }
+    }
+
+    fn event(log_index: i32, vault: &str) -> VaultEvent {
+        VaultEvent {
+            id: id(log_index),
+            vault: vault.to_string(),
+            transaction_hash: "0xtx".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_log_index_is_decoded_from_id() {
+        assert_eq!(log_index(&id(0)), 0);
+        assert_eq!(log_index(&id(300)), 300);
+    }
+
+    #[test]
+    fn test_multi_vault_transaction_is_attributed_in_log_order() {
+        let vaults = vec!["0xb".to_string(), "0xa".to_string()];
+        let events = [event(2, "0xa"), event(5, "0xb"), event(7, "0xa")];
+        let events: Vec<&VaultEvent> = events.iter().collect();
+
+        let states = attribute(vec![status(11), status(10)], &vaults, &events);
+
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[0].vault, "0xa");
+        assert_eq!(states[0].log_index(), 10);
+        assert_eq!(states[1].vault, "0xb");
+
+        // A vault without events cannot be placed, so nothing is attributed
+        let states = attribute(vec![status(10), status(11)], &vaults, &events[..1]);
+        assert!(states.is_empty());
+    }
+}
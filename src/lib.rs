@@ -7,6 +7,7 @@ mod client;
 mod config;
 pub mod euler;
 pub mod morpho;
+mod numeric;
 
 use anyhow::Result;
 use url::Url;
@@ -0,0 +1,497 @@
+//! Morpho Blue events: deposits, withdraws, borrows, repays, transfers and flashloans.
+//!
+//! Events of every kind can be fetched as one stream ordered by `(timestamp, id)`. Pages are
+//! requested with an [`EventCursor`] pointing at the last event already seen, so a stream can
+//! be resumed later without gaps or duplicates.
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::{Decimal, EntityRef};
+use crate::client::{GraphClient, MAX_PAGE_SIZE};
+use crate::numeric::to_f64;
+
+/// Kind of a Morpho Blue event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EventKind {
+    Deposit,
+    Withdraw,
+    Borrow,
+    Repay,
+    Transfer,
+    Flashloan,
+}
+
+impl EventKind {
+    /// Every event kind, in the order they are queried
+    pub const ALL: [EventKind; 6] = [
+        EventKind::Deposit,
+        EventKind::Withdraw,
+        EventKind::Borrow,
+        EventKind::Repay,
+        EventKind::Transfer,
+        EventKind::Flashloan,
+    ];
+
+    fn collection(self) -> &'static str {
+        match self {
+            EventKind::Deposit => "deposits",
+            EventKind::Withdraw => "withdraws",
+            EventKind::Borrow => "borrows",
+            EventKind::Repay => "repays",
+            EventKind::Transfer => "transfers",
+            EventKind::Flashloan => "flashloans",
+        }
+    }
+
+    fn filter_type(self) -> &'static str {
+        match self {
+            EventKind::Deposit => "Deposit_filter",
+            EventKind::Withdraw => "Withdraw_filter",
+            EventKind::Borrow => "Borrow_filter",
+            EventKind::Repay => "Repay_filter",
+            EventKind::Transfer => "Transfer_filter",
+            EventKind::Flashloan => "Flashloan_filter",
+        }
+    }
+
+    fn fields(self) -> &'static str {
+        match self {
+            EventKind::Deposit | EventKind::Withdraw => {
+                "account { id } accountActor { id } isCollateral shares"
+            }
+            EventKind::Borrow | EventKind::Repay => "account { id } accountActor { id } shares",
+            EventKind::Transfer => "sender { id } receiver { id }",
+            EventKind::Flashloan => "account { id } accountActor { id } feeAmount feeAmountUSD",
+        }
+    }
+}
+
+/// Fields shared by every Morpho Blue event
+const COMMON_FIELDS: &str = "id hash logIndex blockNumber timestamp market { id name } \
+                             asset { symbol decimals } amount amountUSD";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventMarket {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventToken {
+    pub symbol: String,
+    pub decimals: i32,
+}
+
+/// Fields shared by every Morpho Blue event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventCommon {
+    pub id: String,
+    pub hash: String,
+    #[serde(rename = "logIndex")]
+    pub log_index: i32,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    pub timestamp: String,
+    pub market: EventMarket,
+    pub asset: EventToken,
+    /// Amount in native units
+    pub amount: String,
+    #[serde(rename = "amountUSD")]
+    pub amount_usd: Decimal,
+}
+
+/// Supply of loan assets or collateral to a market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deposit {
+    #[serde(flatten)]
+    pub common: EventCommon,
+    pub account: EntityRef,
+    #[serde(rename = "accountActor")]
+    pub account_actor: Option<EntityRef>,
+    #[serde(rename = "isCollateral")]
+    pub is_collateral: bool,
+    pub shares: Option<String>,
+}
+
+/// Withdrawal of loan assets or collateral from a market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Withdraw {
+    #[serde(flatten)]
+    pub common: EventCommon,
+    pub account: EntityRef,
+    #[serde(rename = "accountActor")]
+    pub account_actor: Option<EntityRef>,
+    #[serde(rename = "isCollateral")]
+    pub is_collateral: bool,
+    pub shares: Option<String>,
+}
+
+/// Borrow of loan assets from a market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Borrow {
+    #[serde(flatten)]
+    pub common: EventCommon,
+    pub account: EntityRef,
+    #[serde(rename = "accountActor")]
+    pub account_actor: Option<EntityRef>,
+    pub shares: String,
+}
+
+/// Repayment of borrowed assets to a market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Repay {
+    #[serde(flatten)]
+    pub common: EventCommon,
+    pub account: EntityRef,
+    #[serde(rename = "accountActor")]
+    pub account_actor: Option<EntityRef>,
+    pub shares: String,
+}
+
+/// Transfer of a position between accounts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transfer {
+    #[serde(flatten)]
+    pub common: EventCommon,
+    pub sender: EntityRef,
+    pub receiver: EntityRef,
+}
+
+/// Flashloan taken from Morpho Blue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Flashloan {
+    #[serde(flatten)]
+    pub common: EventCommon,
+    pub account: EntityRef,
+    #[serde(rename = "accountActor")]
+    pub account_actor: Option<EntityRef>,
+    #[serde(rename = "feeAmount")]
+    pub fee_amount: Option<String>,
+    #[serde(rename = "feeAmountUSD")]
+    pub fee_amount_usd: Option<Decimal>,
+}
+
+/// Any Morpho Blue event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MorphoEvent {
+    Deposit(Deposit),
+    Withdraw(Withdraw),
+    Borrow(Borrow),
+    Repay(Repay),
+    Transfer(Transfer),
+    Flashloan(Flashloan),
+}
+
+impl MorphoEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            MorphoEvent::Deposit(_) => EventKind::Deposit,
+            MorphoEvent::Withdraw(_) => EventKind::Withdraw,
+            MorphoEvent::Borrow(_) => EventKind::Borrow,
+            MorphoEvent::Repay(_) => EventKind::Repay,
+            MorphoEvent::Transfer(_) => EventKind::Transfer,
+            MorphoEvent::Flashloan(_) => EventKind::Flashloan,
+        }
+    }
+
+    /// Fields shared by every event kind
+    pub fn common(&self) -> &EventCommon {
+        match self {
+            MorphoEvent::Deposit(e) => &e.common,
+            MorphoEvent::Withdraw(e) => &e.common,
+            MorphoEvent::Borrow(e) => &e.common,
+            MorphoEvent::Repay(e) => &e.common,
+            MorphoEvent::Transfer(e) => &e.common,
+            MorphoEvent::Flashloan(e) => &e.common,
+        }
+    }
+
+    /// Account the event applies to; the sender for transfers
+    pub fn account(&self) -> &str {
+        match self {
+            MorphoEvent::Deposit(e) => &e.account.id,
+            MorphoEvent::Withdraw(e) => &e.account.id,
+            MorphoEvent::Borrow(e) => &e.account.id,
+            MorphoEvent::Repay(e) => &e.account.id,
+            MorphoEvent::Transfer(e) => &e.sender.id,
+            MorphoEvent::Flashloan(e) => &e.account.id,
+        }
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.common().timestamp.parse().unwrap_or(0)
+    }
+
+    pub fn amount_usd(&self) -> f64 {
+        to_f64(&self.common().amount_usd)
+    }
+
+    /// Cursor pointing at this event, for resuming a stream after it
+    pub fn cursor(&self) -> EventCursor {
+        EventCursor {
+            timestamp: self.timestamp(),
+            id: self.common().id.clone(),
+        }
+    }
+
+    fn from_value(kind: EventKind, value: Value) -> Result<Self> {
+        let event = match kind {
+            EventKind::Deposit => MorphoEvent::Deposit(serde_json::from_value(value)?),
+            EventKind::Withdraw => MorphoEvent::Withdraw(serde_json::from_value(value)?),
+            EventKind::Borrow => MorphoEvent::Borrow(serde_json::from_value(value)?),
+            EventKind::Repay => MorphoEvent::Repay(serde_json::from_value(value)?),
+            EventKind::Transfer => MorphoEvent::Transfer(serde_json::from_value(value)?),
+            EventKind::Flashloan => MorphoEvent::Flashloan(serde_json::from_value(value)?),
+        };
+        Ok(event)
+    }
+}
+
+/// Position in the `(timestamp, id)` ordered event stream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventCursor {
+    pub timestamp: i64,
+    pub id: String,
+}
+
+/// Selects which events are returned
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    /// Event kinds to include, all kinds when `None` and no events when empty
+    pub kinds: Option<Vec<EventKind>>,
+    pub market: Option<String>,
+    /// Account the event applies to; matches either side of a transfer
+    pub account: Option<String>,
+    /// Inclusive lower bound on the block number
+    pub from_block: Option<u64>,
+    /// Exclusive upper bound on the block number
+    pub to_block: Option<u64>,
+    /// Inclusive lower bound on the event timestamp
+    pub from_time: Option<i64>,
+    /// Exclusive upper bound on the event timestamp
+    pub to_time: Option<i64>,
+    /// Minimum event size in USD
+    pub min_amount_usd: Option<f64>,
+}
+
+impl EventFilter {
+    fn kinds(&self) -> Vec<EventKind> {
+        self.kinds
+            .clone()
+            .unwrap_or_else(|| EventKind::ALL.to_vec())
+    }
+
+    fn to_where(&self, kind: EventKind, after: Option<&EventCursor>) -> Value {
+        let mut base = Map::new();
+        if let Some(market) = &self.market {
+            base.insert("market".to_string(), json!(market.to_lowercase()));
+        }
+        if let Some(from_block) = self.from_block {
+            base.insert("blockNumber_gte".to_string(), json!(from_block.to_string()));
+        }
+        if let Some(to_block) = self.to_block {
+            base.insert("blockNumber_lt".to_string(), json!(to_block.to_string()));
+        }
+        if let Some(from_time) = self.from_time {
+            base.insert("timestamp_gte".to_string(), json!(from_time.to_string()));
+        }
+        if let Some(to_time) = self.to_time {
+            base.insert("timestamp_lt".to_string(), json!(to_time.to_string()));
+        }
+        if let Some(min_amount_usd) = self.min_amount_usd {
+            base.insert(
+                "amountUSD_gte".to_string(),
+                json!(min_amount_usd.to_string()),
+            );
+        }
+
+        let mut clauses = vec![Value::Object(base)];
+        if let Some(account) = &self.account {
+            let account = account.to_lowercase();
+            clauses.push(match kind {
+                EventKind::Transfer => {
+                    json!({ "or": [{ "sender": account }, { "receiver": account }] })
+                }
+                _ => json!({ "account": account }),
+            });
+        }
+        if let Some(cursor) = after {
+            let timestamp = cursor.timestamp.to_string();
+            clauses.push(json!({
+                "or": [
+                    { "timestamp_gt": timestamp },
+                    { "timestamp": timestamp, "id_gt": cursor.id },
+                ]
+            }));
+        }
+
+        json!({ "and": clauses })
+    }
+}
+
+/// A page of the merged event stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventPage {
+    pub events: Vec<MorphoEvent>,
+    /// Cursor to pass to the next call, `None` once the stream is exhausted
+    pub next_cursor: Option<EventCursor>,
+}
+
+/// Fetch up to `page_size` events of all selected kinds that come after `after`, ordered by
+/// `(timestamp, id)`
+pub async fn fetch_event_page(
+    client: &GraphClient,
+    filter: &EventFilter,
+    after: Option<&EventCursor>,
+    page_size: usize,
+) -> Result<EventPage> {
+    let kinds = filter.kinds();
+    if kinds.is_empty() {
+        return Ok(EventPage {
+            events: Vec::new(),
+            next_cursor: None,
+        });
+    }
+    let page_size = page_size.clamp(1, MAX_PAGE_SIZE);
+
+    let declarations: Vec<String> = kinds
+        .iter()
+        .map(|kind| format!("$where_{}: {}!", kind.collection(), kind.filter_type()))
+        .collect();
+    let selections: Vec<String> = kinds
+        .iter()
+        .map(|kind| {
+            format!(
+                "{collection}(first: $first, orderBy: timestamp, orderDirection: asc, \
+                 where: $where_{collection}) {{ {common} {fields} }}",
+                collection = kind.collection(),
+                common = COMMON_FIELDS,
+                fields = kind.fields(),
+            )
+        })
+        .collect();
+    let query = format!(
+        "query MorphoEvents($first: Int!, {}) {{ {} }}",
+        declarations.join(", "),
+        selections.join(" ")
+    );
+
+    let mut variables = json!({ "first": page_size });
+    for kind in &kinds {
+        variables[format!("where_{}", kind.collection())] = filter.to_where(*kind, after);
+    }
+
+    let data: Value = client.query_raw(&query, variables).await?;
+
+    let mut events = Vec::new();
+    let mut exhausted = true;
+    for kind in &kinds {
+        let entities = match data.get(kind.collection()) {
+            Some(Value::Array(entities)) => entities.clone(),
+            _ => return Err(anyhow!("Missing '{}' in response", kind.collection())),
+        };
+        if entities.len() == page_size {
+            exhausted = false;
+        }
+        for entity in entities {
+            events
+                .push(MorphoEvent::from_value(*kind, entity).map_err(|e| {
+                    anyhow!("Failed to parse '{}' event: {}", kind.collection(), e)
+                })?);
+        }
+    }
+
+    events.sort_by(|a, b| {
+        a.timestamp()
+            .cmp(&b.timestamp())
+            .then_with(|| a.common().id.cmp(&b.common().id))
+    });
+    // Events past the page size may be preceded by unfetched events of another kind
+    if events.len() > page_size {
+        events.truncate(page_size);
+        exhausted = false;
+    }
+
+    debug!("Fetched page of {} Morpho events", events.len());
+    let next_cursor = if exhausted {
+        None
+    } else {
+        events.last().map(MorphoEvent::cursor)
+    };
+
+    Ok(EventPage {
+        events,
+        next_cursor,
+    })
+}
+
+/// Fetch every event matching `filter`, oldest first, stopping after `limit` events if given
+pub async fn fetch_events(
+    client: &GraphClient,
+    filter: &EventFilter,
+    limit: Option<usize>,
+) -> Result<Vec<MorphoEvent>> {
+    let mut events = Vec::new();
+    let mut cursor: Option<EventCursor> = None;
+
+    loop {
+        let page_size = match limit {
+            Some(limit) => (limit - events.len()).min(MAX_PAGE_SIZE),
+            None => MAX_PAGE_SIZE,
+        };
+        if page_size == 0 {
+            break;
+        }
+
+        let page = fetch_event_page(client, filter, cursor.as_ref(), page_size).await?;
+        events.extend(page.events);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_where_matches_both_sides_of_transfers_and_resumes_after_cursor() {
+        let filter = EventFilter {
+            account: Some("0xABC".to_string()),
+            min_amount_usd: Some(1000.0),
+            ..Default::default()
+        };
+        let cursor = EventCursor {
+            timestamp: 1700000000,
+            id: "0x01".to_string(),
+        };
+
+        let transfer_where = filter.to_where(EventKind::Transfer, Some(&cursor));
+        assert_eq!(
+            transfer_where,
+            json!({
+                "and": [
+                    { "amountUSD_gte": "1000" },
+                    { "or": [{ "sender": "0xabc" }, { "receiver": "0xabc" }] },
+                    { "or": [
+                        { "timestamp_gt": "1700000000" },
+                        { "timestamp": "1700000000", "id_gt": "0x01" },
+                    ] },
+                ]
+            })
+        );
+
+        let deposit_where = filter.to_where(EventKind::Deposit, None);
+        assert_eq!(
+            deposit_where,
+            json!({ "and": [{ "amountUSD_gte": "1000" }, { "account": "0xabc" }] })
+        );
+    }
+}
@@ -0,0 +1,716 @@
+//! MetaMorpho governance: pending caps, timelocks, guardians and queue changes.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::EntityRef;
+use crate::client::GraphClient;
+
+/// Longest timelock a MetaMorpho vault accepts, used as the default watcher lookback
+const MAX_TIMELOCK_SECONDS: i64 = 14 * 24 * 60 * 60;
+
+/// Vault fields selected alongside each governance action
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GovernedVault {
+    pub id: String,
+    pub name: String,
+    pub timelock: String,
+    pub guardian: Option<EntityRef>,
+}
+
+/// A vault market referenced by a pending cap or a queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueMarket {
+    /// Id of the `MetaMorphoMarket` entity
+    pub id: String,
+    pub cap: String,
+    pub market: EntityRef,
+}
+
+/// A submitted supply cap change for one of a vault's markets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingCap {
+    pub id: String,
+    pub cap: String,
+    #[serde(rename = "metaMorpho")]
+    pub meta_morpho: GovernedVault,
+    #[serde(rename = "metaMorphoMarket")]
+    pub meta_morpho_market: QueueMarket,
+    #[serde(rename = "isNewMarket")]
+    pub is_new_market: bool,
+    #[serde(rename = "submittedAt")]
+    pub submitted_at: String,
+    #[serde(rename = "validAt")]
+    pub valid_at: String,
+    pub status: String,
+}
+
+/// A submitted timelock change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTimelock {
+    pub id: String,
+    pub timelock: String,
+    #[serde(rename = "metaMorpho")]
+    pub meta_morpho: GovernedVault,
+    #[serde(rename = "submittedAt")]
+    pub submitted_at: String,
+    #[serde(rename = "validAt")]
+    pub valid_at: String,
+    pub status: String,
+}
+
+/// A submitted guardian change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingGuardian {
+    pub id: String,
+    pub guardian: String,
+    #[serde(rename = "metaMorpho")]
+    pub meta_morpho: GovernedVault,
+    #[serde(rename = "submittedAt")]
+    pub submitted_at: String,
+    #[serde(rename = "validAt")]
+    pub valid_at: String,
+    pub status: String,
+}
+
+/// A supply or withdraw queue update
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewQueue {
+    pub id: String,
+    #[serde(rename = "queueType")]
+    pub queue_type: String,
+    pub caller: EntityRef,
+    #[serde(rename = "metaMorpho")]
+    pub meta_morpho: GovernedVault,
+    #[serde(rename = "submittedAt")]
+    pub submitted_at: String,
+    #[serde(rename = "previousQueue")]
+    pub previous_queue: Vec<QueueMarket>,
+    #[serde(rename = "newQueue")]
+    pub new_queue: Vec<QueueMarket>,
+    #[serde(rename = "removedMarkets")]
+    pub removed_markets: Vec<QueueMarket>,
+    #[serde(rename = "addedMarkets")]
+    pub added_markets: Vec<QueueMarket>,
+}
+
+/// Governance actions submitted within a time window, newest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GovernanceActions {
+    #[serde(rename = "pendingCaps")]
+    pub pending_caps: Vec<PendingCap>,
+    #[serde(rename = "pendingTimelocks")]
+    pub pending_timelocks: Vec<PendingTimelock>,
+    #[serde(rename = "pendingGuardians")]
+    pub pending_guardians: Vec<PendingGuardian>,
+    #[serde(rename = "newQueues")]
+    pub new_queues: Vec<NewQueue>,
+}
+
+impl GovernanceActions {
+    /// Keep only the actions that are still waiting for their timelock to elapse
+    pub fn pending_only(mut self) -> Self {
+        self.pending_caps.retain(|cap| cap.status == "PENDING");
+        self.pending_timelocks
+            .retain(|timelock| timelock.status == "PENDING");
+        self.pending_guardians
+            .retain(|guardian| guardian.status == "PENDING");
+        self.new_queues.clear();
+        self
+    }
+}
+
+/// Kind of a timelocked governance action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GovernanceActionKind {
+    Cap,
+    Timelock,
+    Guardian,
+}
+
+/// A change in a vault's governance state detected by [`GovernanceWatcher`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GovernanceEvent {
+    CapIncreaseSubmitted {
+        vault: String,
+        market: String,
+        current_cap: String,
+        new_cap: String,
+        is_new_market: bool,
+        valid_at: String,
+    },
+    CapIncreaseAccepted {
+        vault: String,
+        market: String,
+        cap: String,
+    },
+    TimelockShortenSubmitted {
+        vault: String,
+        current_timelock: String,
+        new_timelock: String,
+        valid_at: String,
+    },
+    TimelockShortened {
+        vault: String,
+        timelock: String,
+    },
+    GuardianChangeSubmitted {
+        vault: String,
+        current_guardian: Option<String>,
+        new_guardian: String,
+        valid_at: String,
+    },
+    GuardianChanged {
+        vault: String,
+        guardian: String,
+    },
+    SupplyQueueReordered {
+        vault: String,
+        caller: String,
+        previous_queue: Vec<String>,
+        new_queue: Vec<String>,
+        added_markets: Vec<String>,
+        removed_markets: Vec<String>,
+    },
+    WithdrawQueueReordered {
+        vault: String,
+        caller: String,
+        previous_queue: Vec<String>,
+        new_queue: Vec<String>,
+        added_markets: Vec<String>,
+        removed_markets: Vec<String>,
+    },
+    /// A pending action was rejected by the guardian or overridden by a new submission
+    PendingActionRevoked {
+        vault: String,
+        kind: GovernanceActionKind,
+        id: String,
+        status: String,
+    },
+}
+
+/// Fragments shared by the governance action queries
+const GOVERNANCE_FRAGMENTS: &str = r#"
+    fragment GovernedVault on MetaMorpho {
+        id
+        name
+        timelock
+        guardian {
+            id
+        }
+    }
+
+    fragment QueueMarket on MetaMorphoMarket {
+        id
+        cap
+        market {
+            id
+        }
+    }
+"#;
+
+/// Fetch governance actions submitted at or after `since`, for one vault or for all vaults
+pub async fn fetch_governance_actions(
+    client: &GraphClient,
+    vault: Option<&str>,
+    since: i64,
+) -> Result<GovernanceActions> {
+    let caps_query = format!(
+        r#"
+    query MorphoPendingCaps($first: Int!, $lastId: Bytes!, $where: PendingCap_filter!) {{
+        pendingCaps(first: $first, orderBy: id, orderDirection: asc, where: {{ and: [{{ id_gt: $lastId }}, $where] }}) {{
+            id
+            cap
+            metaMorpho {{
+                ...GovernedVault
+            }}
+            metaMorphoMarket {{
+                ...QueueMarket
+            }}
+            isNewMarket
+            submittedAt
+            validAt
+            status
+        }}
+    }}
+    {}"#,
+        GOVERNANCE_FRAGMENTS
+    );
+    let timelocks_query = format!(
+        r#"
+    query MorphoPendingTimelocks($first: Int!, $lastId: Bytes!, $where: PendingTimelock_filter!) {{
+        pendingTimelocks(first: $first, orderBy: id, orderDirection: asc, where: {{ and: [{{ id_gt: $lastId }}, $where] }}) {{
+            id
+            timelock
+            metaMorpho {{
+                ...GovernedVault
+            }}
+            submittedAt
+            validAt
+            status
+        }}
+    }}
+    {}"#,
+        GOVERNANCE_FRAGMENTS
+    );
+    let guardians_query = format!(
+        r#"
+    query MorphoPendingGuardians($first: Int!, $lastId: Bytes!, $where: PendingGuardian_filter!) {{
+        pendingGuardians(first: $first, orderBy: id, orderDirection: asc, where: {{ and: [{{ id_gt: $lastId }}, $where] }}) {{
+            id
+            guardian
+            metaMorpho {{
+                ...GovernedVault
+            }}
+            submittedAt
+            validAt
+            status
+        }}
+    }}
+    {}"#,
+        GOVERNANCE_FRAGMENTS
+    );
+    let queues_query = format!(
+        r#"
+    query MorphoNewQueues($first: Int!, $lastId: Bytes!, $where: NewQueue_filter!) {{
+        newQueues(first: $first, orderBy: id, orderDirection: asc, where: {{ and: [{{ id_gt: $lastId }}, $where] }}) {{
+            id
+            queueType
+            caller {{
+                id
+            }}
+            metaMorpho {{
+                ...GovernedVault
+            }}
+            submittedAt
+            previousQueue {{
+                ...QueueMarket
+            }}
+            newQueue {{
+                ...QueueMarket
+            }}
+            removedMarkets {{
+                ...QueueMarket
+            }}
+            addedMarkets {{
+                ...QueueMarket
+            }}
+        }}
+    }}
+    {}"#,
+        GOVERNANCE_FRAGMENTS
+    );
+
+    let mut filter = Map::new();
+    filter.insert("submittedAt_gte".to_string(), json!(since.to_string()));
+    if let Some(vault) = vault {
+        filter.insert("metaMorpho".to_string(), json!(vault.to_lowercase()));
+    }
+    let variables = json!({ "where": Value::Object(filter) });
+
+    let mut actions = GovernanceActions {
+        pending_caps: client
+            .query_paginated(&caps_query, "pendingCaps", variables.clone(), None)
+            .await?,
+        pending_timelocks: client
+            .query_paginated(
+                &timelocks_query,
+                "pendingTimelocks",
+                variables.clone(),
+                None,
+            )
+            .await?,
+        pending_guardians: client
+            .query_paginated(
+                &guardians_query,
+                "pendingGuardians",
+                variables.clone(),
+                None,
+            )
+            .await?,
+        new_queues: client
+            .query_paginated(&queues_query, "newQueues", variables, None)
+            .await?,
+    };
+    let newest_first =
+        |submitted_at: &str| std::cmp::Reverse(submitted_at.parse::<i64>().unwrap_or(0));
+    actions
+        .pending_caps
+        .sort_by_key(|cap| newest_first(&cap.submitted_at));
+    actions
+        .pending_timelocks
+        .sort_by_key(|timelock| newest_first(&timelock.submitted_at));
+    actions
+        .pending_guardians
+        .sort_by_key(|guardian| newest_first(&guardian.submitted_at));
+    actions
+        .new_queues
+        .sort_by_key(|queue| newest_first(&queue.submitted_at));
+    Ok(actions)
+}
+
+/// Last known status of a timelocked action, with the time it was submitted
+#[derive(Debug, Clone)]
+struct TrackedAction {
+    status: String,
+    submitted_at: i64,
+}
+
+/// Polls the subgraph and turns new or updated governance actions into [`GovernanceEvent`]s
+pub struct GovernanceWatcher {
+    client: GraphClient,
+    vault: Option<String>,
+    /// Only actions submitted at or after this time are fetched
+    since: i64,
+    started_at: i64,
+    /// Newest submission time seen so far
+    newest_seen: i64,
+    statuses: HashMap<String, TrackedAction>,
+    /// Queue changes already reported, with their submission time
+    seen_queues: HashMap<String, i64>,
+}
+
+impl GovernanceWatcher {
+    /// Create a watcher for one vault, or for every vault when `vault` is `None`
+    pub fn new(client: GraphClient, vault: Option<&str>) -> Self {
+        let now = chrono::Utc::now().timestamp();
+        GovernanceWatcher {
+            client,
+            vault: vault.map(str::to_lowercase),
+            since: now - MAX_TIMELOCK_SECONDS,
+            started_at: now,
+            newest_seen: now - MAX_TIMELOCK_SECONDS,
+            statuses: HashMap::new(),
+            seen_queues: HashMap::new(),
+        }
+    }
+
+    /// Fetch the latest governance actions and return the events since the previous poll.
+    ///
+    /// The first poll reports every action that is still pending and every queue change
+    /// made after the watcher was created.
+    pub async fn poll(&mut self) -> Result<Vec<GovernanceEvent>> {
+        let actions =
+            fetch_governance_actions(&self.client, self.vault.as_deref(), self.since).await?;
+        let events = self.process(actions);
+        self.advance();
+        debug!("Governance poll produced {} events", events.len());
+        Ok(events)
+    }
+
+    /// Poll forever at the given interval, handing every event to `on_event`.
+    /// Failed polls are logged and retried on the next tick.
+    pub async fn run<F>(&mut self, interval: Duration, mut on_event: F) -> Result<()>
+    where
+        F: FnMut(GovernanceEvent),
+    {
+        info!("Watching MetaMorpho governance every {:?}", interval);
+        loop {
+            match self.poll().await {
+                Ok(events) => events.into_iter().for_each(&mut on_event),
+                Err(e) => error!("Failed to poll governance actions: {}", e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    fn process(&mut self, actions: GovernanceActions) -> Vec<GovernanceEvent> {
+        let mut events = Vec::new();
+
+        for cap in actions.pending_caps.into_iter().rev() {
+            let vault = cap.meta_morpho.id.clone();
+            let market = cap.meta_morpho_market.market.id.clone();
+            match self.transition(&cap.id, &cap.status, &cap.submitted_at) {
+                Some(status) if status == "PENDING" => {
+                    events.push(GovernanceEvent::CapIncreaseSubmitted {
+                        vault,
+                        market,
+                        current_cap: cap.meta_morpho_market.cap,
+                        new_cap: cap.cap,
+                        is_new_market: cap.is_new_market,
+                        valid_at: cap.valid_at,
+                    })
+                }
+                Some(status) if status == "ACCEPTED" => {
+                    events.push(GovernanceEvent::CapIncreaseAccepted {
+                        vault,
+                        market,
+                        cap: cap.cap,
+                    })
+                }
+                Some(status) => events.push(GovernanceEvent::PendingActionRevoked {
+                    vault,
+                    kind: GovernanceActionKind::Cap,
+                    id: cap.id,
+                    status,
+                }),
+                None => {}
+            }
+        }
+
+        for timelock in actions.pending_timelocks.into_iter().rev() {
+            let vault = timelock.meta_morpho.id.clone();
+            // Timelock increases apply immediately, so only decreases are ever pending
+            match self.transition(&timelock.id, &timelock.status, &timelock.submitted_at) {
+                Some(status) if status == "PENDING" => {
+                    events.push(GovernanceEvent::TimelockShortenSubmitted {
+                        vault,
+                        current_timelock: timelock.meta_morpho.timelock,
+                        new_timelock: timelock.timelock,
+                        valid_at: timelock.valid_at,
+                    })
+                }
+                Some(status) if status == "ACCEPTED" => {
+                    events.push(GovernanceEvent::TimelockShortened {
+                        vault,
+                        timelock: timelock.timelock,
+                    })
+                }
+                Some(status) => events.push(GovernanceEvent::PendingActionRevoked {
+                    vault,
+                    kind: GovernanceActionKind::Timelock,
+                    id: timelock.id,
+                    status,
+                }),
+                None => {}
+            }
+        }
+
+        for guardian in actions.pending_guardians.into_iter().rev() {
+            let vault = guardian.meta_morpho.id.clone();
+            match self.transition(&guardian.id, &guardian.status, &guardian.submitted_at) {
+                Some(status) if status == "PENDING" => {
+                    events.push(GovernanceEvent::GuardianChangeSubmitted {
+                        vault,
+                        current_guardian: guardian.meta_morpho.guardian.map(|g| g.id),
+                        new_guardian: guardian.guardian,
+                        valid_at: guardian.valid_at,
+                    })
+                }
+                Some(status) if status == "ACCEPTED" => {
+                    events.push(GovernanceEvent::GuardianChanged {
+                        vault,
+                        guardian: guardian.guardian,
+                    })
+                }
+                Some(status) => events.push(GovernanceEvent::PendingActionRevoked {
+                    vault,
+                    kind: GovernanceActionKind::Guardian,
+                    id: guardian.id,
+                    status,
+                }),
+                None => {}
+            }
+        }
+
+        for queue in actions.new_queues.into_iter().rev() {
+            let submitted_at = queue.submitted_at.parse::<i64>().unwrap_or(0);
+            self.newest_seen = self.newest_seen.max(submitted_at);
+            if self
+                .seen_queues
+                .insert(queue.id.clone(), submitted_at)
+                .is_some()
+            {
+                continue;
+            }
+            if submitted_at < self.started_at {
+                continue;
+            }
+
+            let market_ids =
+                |markets: Vec<QueueMarket>| markets.into_iter().map(|m| m.market.id).collect();
+            let vault = queue.meta_morpho.id;
+            let caller = queue.caller.id;
+            let previous_queue = market_ids(queue.previous_queue);
+            let new_queue = market_ids(queue.new_queue);
+            let added_markets = market_ids(queue.added_markets);
+            let removed_markets = market_ids(queue.removed_markets);
+
+            events.push(if queue.queue_type == "SUPPLY_QUEUE" {
+                GovernanceEvent::SupplyQueueReordered {
+                    vault,
+                    caller,
+                    previous_queue,
+                    new_queue,
+                    added_markets,
+                    removed_markets,
+                }
+            } else {
+                GovernanceEvent::WithdrawQueueReordered {
+                    vault,
+                    caller,
+                    previous_queue,
+                    new_queue,
+                    added_markets,
+                    removed_markets,
+                }
+            });
+        }
+
+        events
+    }
+
+    /// Record the status of a pending action, returning it if the watcher should report it.
+    /// Unknown actions are reported while pending, or already settled when submitted after
+    /// the watcher started; known ones whenever their status moves.
+    fn transition(&mut self, id: &str, status: &str, submitted_at: &str) -> Option<String> {
+        let submitted_at = submitted_at.parse::<i64>().unwrap_or(0);
+        self.newest_seen = self.newest_seen.max(submitted_at);
+        let previous = self.statuses.insert(
+            id.to_string(),
+            TrackedAction {
+                status: status.to_string(),
+                submitted_at,
+            },
+        );
+        match previous {
+            None if status == "PENDING" || submitted_at >= self.started_at => {
+                Some(status.to_string())
+            }
+            Some(previous) if previous.status != status => Some(status.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Move the cursor up to the oldest action still pending, or to the newest submission
+    /// seen when nothing is pending, and forget settled actions the cursor has moved past.
+    /// Settled actions at or after the cursor are kept, as they are fetched again and must
+    /// not be reported twice.
+    fn advance(&mut self) {
+        self.since = self
+            .statuses
+            .values()
+            .filter(|action| action.status == "PENDING")
+            .map(|action| action.submitted_at)
+            .min()
+            .unwrap_or(self.newest_seen)
+            .min(self.newest_seen)
+            .max(self.since);
+        let since = self.since;
+        self.statuses
+            .retain(|_, action| action.status == "PENDING" || action.submitted_at >= since);
+        self.seen_queues
+            .retain(|_, submitted_at| *submitted_at >= since);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watcher() -> GovernanceWatcher {
+        let client =
+            GraphClient::new(url::Url::parse("http://localhost").unwrap()).expect("client");
+        let mut watcher = GovernanceWatcher::new(client, None);
+        watcher.started_at = 0;
+        watcher.since = 0;
+        watcher.newest_seen = 0;
+        watcher
+    }
+
+    fn pending_cap(status: &str) -> PendingCap {
+        PendingCap {
+            id: "0xcap".to_string(),
+            cap: "2000".to_string(),
+            meta_morpho: GovernedVault {
+                id: "0xvault".to_string(),
+                name: "Vault".to_string(),
+                timelock: "86400".to_string(),
+                guardian: None,
+            },
+            meta_morpho_market: QueueMarket {
+                id: "0xvaultmarket".to_string(),
+                cap: "1000".to_string(),
+                market: EntityRef {
+                    id: "0xmarket".to_string(),
+                },
+            },
+            is_new_market: false,
+            submitted_at: "100".to_string(),
+            valid_at: "86500".to_string(),
+            status: status.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_cap_submitted_then_accepted() {
+        let mut watcher = watcher();
+
+        let events = watcher.process(GovernanceActions {
+            pending_caps: vec![pending_cap("PENDING")],
+            ..Default::default()
+        });
+        assert!(matches!(
+            events.as_slice(),
+            [GovernanceEvent::CapIncreaseSubmitted { new_cap, .. }] if new_cap == "2000"
+        ));
+
+        let events = watcher.process(GovernanceActions {
+            pending_caps: vec![pending_cap("PENDING")],
+            ..Default::default()
+        });
+        assert!(events.is_empty());
+
+        let events = watcher.process(GovernanceActions {
+            pending_caps: vec![pending_cap("ACCEPTED")],
+            ..Default::default()
+        });
+        assert!(matches!(
+            events.as_slice(),
+            [GovernanceEvent::CapIncreaseAccepted { cap, .. }] if cap == "2000"
+        ));
+    }
+
+    #[test]
+    fn test_advance_prunes_settled_actions_and_moves_cursor() {
+        let mut watcher = watcher();
+        let mut accepted = pending_cap("ACCEPTED");
+        accepted.id = "0xaccepted".to_string();
+        accepted.submitted_at = "500".to_string();
+
+        watcher.process(GovernanceActions {
+            pending_caps: vec![accepted.clone(), pending_cap("PENDING")],
+            ..Default::default()
+        });
+        watcher.advance();
+        assert_eq!(watcher.statuses.len(), 2);
+        assert_eq!(watcher.since, 100);
+
+        let events = watcher.process(GovernanceActions {
+            pending_caps: vec![accepted, pending_cap("ACCEPTED")],
+            ..Default::default()
+        });
+        watcher.advance();
+        assert_eq!(events.len(), 1);
+        assert_eq!(watcher.statuses.len(), 1);
+        assert!(watcher.statuses.contains_key("0xaccepted"));
+        assert_eq!(watcher.since, 500);
+    }
+
+    #[test]
+    fn test_actions_settled_between_polls_are_reported() {
+        let mut started_earlier = watcher();
+        let events = started_earlier.process(GovernanceActions {
+            pending_caps: vec![pending_cap("ACCEPTED")],
+            ..Default::default()
+        });
+        assert!(matches!(
+            events.as_slice(),
+            [GovernanceEvent::CapIncreaseAccepted { cap, .. }] if cap == "2000"
+        ));
+
+        // Submitted before the watcher started, so part of the state it started from
+        let mut started_later = watcher();
+        started_later.started_at = 1_000;
+        let events = started_later.process(GovernanceActions {
+            pending_caps: vec![pending_cap("ACCEPTED")],
+            ..Default::default()
+        });
+        assert!(events.is_empty());
+    }
+}
@@ -0,0 +1,378 @@
+//! Position snapshot history and a PnL calculator built on it.
+//!
+//! Between two snapshots of a position, the change in value is split into:
+//!
+//! - interest: growth of the balance beyond the principal, `(b1 - p1) - (b0 - p0)`, or
+//!   `b0 * (i1 / i0 - 1)` from the position index when the principal is not tracked
+//! - flows: the rest of the balance change, i.e. deposits, withdrawals, borrows and repays
+//! - price: revaluation of the starting balance, `b0 * (price1 - price0)`
+//!
+//! Interest and flows are valued at the later price. Borrow positions count negatively, so
+//! interest paid and a rising debt asset price both reduce PnL.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::Decimal;
+use crate::client::GraphClient;
+use crate::numeric::to_f64;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotAsset {
+    pub symbol: String,
+    pub decimals: i32,
+}
+
+/// Position fields selected alongside each snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotPosition {
+    pub id: String,
+    /// `COLLATERAL`, `SUPPLIER` or `BORROWER`
+    pub side: String,
+    pub asset: SnapshotAsset,
+}
+
+/// State of a position after a transaction that touched it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionSnapshot {
+    pub id: String,
+    pub hash: String,
+    #[serde(rename = "logIndex")]
+    pub log_index: i32,
+    pub position: SnapshotPosition,
+    /// Balance in native units
+    pub balance: String,
+    #[serde(rename = "balanceUSD")]
+    pub balance_usd: Decimal,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    pub timestamp: String,
+    /// Balance without accrued interest, in native units
+    pub principal: Option<String>,
+    /// Supply or borrow index of the market, depending on the position side
+    pub index: Option<String>,
+}
+
+impl PositionSnapshot {
+    /// Balance in whole tokens
+    pub fn amount(&self) -> f64 {
+        to_f64(&self.balance) / 10f64.powi(self.position.asset.decimals)
+    }
+
+    /// Asset price implied by the USD balance, if the position is not empty
+    pub fn price_usd(&self) -> Option<f64> {
+        let amount = self.amount();
+        if amount > 0.0 {
+            Some(to_f64(&self.balance_usd) / amount)
+        } else {
+            None
+        }
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.timestamp.parse().unwrap_or(0)
+    }
+}
+
+/// PnL of an account for one day, summed over its positions
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DailyPnl {
+    /// Start of the day, in seconds since the Unix epoch
+    pub day: i64,
+    /// Interest earned minus interest paid
+    pub interest_usd: f64,
+    /// Value moved in or out by deposits, withdrawals, borrows and repays
+    pub flows_usd: f64,
+    /// Revaluation of balances from asset price changes
+    pub price_usd: f64,
+    /// Interest plus price effect
+    pub pnl_usd: f64,
+    /// Lending minus borrowing balances at the end of the day
+    pub net_balance_usd: f64,
+}
+
+/// Per-day PnL of an account's lending and borrowing positions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountPnl {
+    pub account: String,
+    /// Oldest day first
+    pub days: Vec<DailyPnl>,
+    pub total_interest_usd: f64,
+    pub total_flows_usd: f64,
+    pub total_price_usd: f64,
+    pub total_pnl_usd: f64,
+}
+
+const SNAPSHOT_FIELDS: &str = r#"
+            id
+            hash
+            logIndex
+            position {
+                id
+                side
+                asset {
+                    symbol
+                    decimals
+                }
+            }
+            balance
+            balanceUSD
+            blockNumber
+            timestamp
+            principal
+            index
+"#;
+
+/// Fetch the snapshots of a position with a timestamp in `range`, oldest first
+pub async fn fetch_position_history(
+    client: &GraphClient,
+    position_id: &str,
+    range: Range<i64>,
+) -> Result<Vec<PositionSnapshot>> {
+    let query = format!(
+        r#"
+    query MorphoPositionHistory($first: Int!, $lastId: ID!, $position: String!, $from: BigInt!, $to: BigInt!) {{
+        positionSnapshots(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: {{ id_gt: $lastId, position: $position, timestamp_gte: $from, timestamp_lt: $to }}
+        ) {{{}}}
+    }}
+    "#,
+        SNAPSHOT_FIELDS
+    );
+
+    let variables = json!({
+        "position": normalize_position_id(position_id),
+        "from": range.start.to_string(),
+        "to": range.end.to_string(),
+    });
+
+    let mut snapshots: Vec<PositionSnapshot> = client
+        .query_paginated(&query, "positionSnapshots", variables, None)
+        .await?;
+    sort_snapshots(&mut snapshots);
+    Ok(snapshots)
+}
+
+/// Fetch the snapshots of every position of an account within `range` and compute its daily
+/// PnL. Each position's first snapshot in the range is the starting point.
+pub async fn fetch_account_pnl(
+    client: &GraphClient,
+    account: &str,
+    range: Range<i64>,
+) -> Result<AccountPnl> {
+    let query = format!(
+        r#"
+    query MorphoAccountPositionHistory($first: Int!, $lastId: ID!, $account: String!, $from: BigInt!, $to: BigInt!) {{
+        positionSnapshots(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: {{ id_gt: $lastId, account: $account, timestamp_gte: $from, timestamp_lt: $to }}
+        ) {{{}}}
+    }}
+    "#,
+        SNAPSHOT_FIELDS
+    );
+
+    let account = account.to_lowercase();
+    let variables = json!({
+        "account": account,
+        "from": range.start.to_string(),
+        "to": range.end.to_string(),
+    });
+
+    let snapshots: Vec<PositionSnapshot> = client
+        .query_paginated(&query, "positionSnapshots", variables, None)
+        .await?;
+    Ok(compute_account_pnl(account, snapshots))
+}
+
+/// Lowercase the account and market addresses of a position id, which has the form
+/// `{account}-{market}-{SIDE}-{counter}`, leaving the uppercase side as is
+fn normalize_position_id(position_id: &str) -> String {
+    position_id
+        .split('-')
+        .map(|part| {
+            if part.starts_with("0x") || part.starts_with("0X") {
+                part.to_lowercase()
+            } else {
+                part.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn sort_snapshots(snapshots: &mut [PositionSnapshot]) {
+    snapshots.sort_by_key(|snapshot| (snapshot.timestamp(), snapshot.log_index));
+}
+
+/// Compute an account's daily PnL from the snapshots of its positions
+pub fn compute_account_pnl(account: String, snapshots: Vec<PositionSnapshot>) -> AccountPnl {
+    let mut by_position: HashMap<String, Vec<PositionSnapshot>> = HashMap::new();
+    for snapshot in snapshots {
+        by_position
+            .entry(snapshot.position.id.clone())
+            .or_default()
+            .push(snapshot);
+    }
+
+    let mut days: BTreeMap<i64, DailyPnl> = BTreeMap::new();
+    // Signed end-of-day balance of each position, carried forward over days without snapshots
+    let mut balances: BTreeMap<i64, HashMap<String, f64>> = BTreeMap::new();
+
+    for (position_id, mut snapshots) in by_position {
+        sort_snapshots(&mut snapshots);
+        let sign = if snapshots[0].position.side == "BORROWER" {
+            -1.0
+        } else {
+            1.0
+        };
+
+        let mut price = snapshots[0].price_usd();
+        for pair in snapshots.windows(2) {
+            let (before, after) = (&pair[0], &pair[1]);
+            let price_before = price.or_else(|| after.price_usd()).unwrap_or(0.0);
+            let price_after = after.price_usd().unwrap_or(price_before);
+            price = Some(price_after);
+
+            let day = after.timestamp() / SECONDS_PER_DAY * SECONDS_PER_DAY;
+            let entry = days.entry(day).or_insert_with(|| DailyPnl {
+                day,
+                ..Default::default()
+            });
+
+            let interest = interest_between(before, after);
+            let flows = after.amount() - before.amount() - interest;
+            entry.interest_usd += sign * interest * price_after;
+            entry.flows_usd += sign * flows * price_after;
+            entry.price_usd += sign * before.amount() * (price_after - price_before);
+        }
+
+        for snapshot in &snapshots {
+            let day = snapshot.timestamp() / SECONDS_PER_DAY * SECONDS_PER_DAY;
+            days.entry(day).or_insert_with(|| DailyPnl {
+                day,
+                ..Default::default()
+            });
+            balances
+                .entry(day)
+                .or_default()
+                .insert(position_id.clone(), sign * to_f64(&snapshot.balance_usd));
+        }
+    }
+
+    let mut latest: HashMap<String, f64> = HashMap::new();
+    let mut series: Vec<DailyPnl> = Vec::with_capacity(days.len());
+    for (day, mut pnl) in days {
+        if let Some(updates) = balances.remove(&day) {
+            latest.extend(updates);
+        }
+        pnl.pnl_usd = pnl.interest_usd + pnl.price_usd;
+        pnl.net_balance_usd = latest.values().sum();
+        series.push(pnl);
+    }
+
+    AccountPnl {
+        account,
+        total_interest_usd: series.iter().map(|d| d.interest_usd).sum(),
+        total_flows_usd: series.iter().map(|d| d.flows_usd).sum(),
+        total_price_usd: series.iter().map(|d| d.price_usd).sum(),
+        total_pnl_usd: series.iter().map(|d| d.pnl_usd).sum(),
+        days: series,
+    }
+}
+
+/// Interest accrued between two snapshots of a position, in whole tokens
+fn interest_between(before: &PositionSnapshot, after: &PositionSnapshot) -> f64 {
+    let scale = 10f64.powi(after.position.asset.decimals);
+    match (&before.principal, &after.principal) {
+        (Some(p0), Some(p1)) => {
+            ((to_f64(&after.balance) - to_f64(p1)) - (to_f64(&before.balance) - to_f64(p0))) / scale
+        }
+        _ => match (&before.index, &after.index) {
+            (Some(i0), Some(i1)) if to_f64(i0) > 0.0 => {
+                before.amount() * (to_f64(i1) / to_f64(i0) - 1.0)
+            }
+            _ => 0.0,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_position_id_keeps_side() {
+        assert_eq!(
+            normalize_position_id("0xABcd-0xEF01-BORROWER-0"),
+            "0xabcd-0xef01-BORROWER-0"
+        );
+    }
+
+    fn snapshot(
+        side: &str,
+        timestamp: i64,
+        balance: &str,
+        balance_usd: &str,
+        principal: &str,
+    ) -> PositionSnapshot {
+        PositionSnapshot {
+            id: format!("0x{}-{}", side, timestamp),
+            hash: "0xhash".to_string(),
+            log_index: 0,
+            position: SnapshotPosition {
+                id: format!("0xaccount-0xmarket-{}", side),
+                side: side.to_string(),
+                asset: SnapshotAsset {
+                    symbol: "USDC".to_string(),
+                    decimals: 6,
+                },
+            },
+            balance: balance.to_string(),
+            balance_usd: balance_usd.to_string(),
+            block_number: "1".to_string(),
+            timestamp: timestamp.to_string(),
+            principal: Some(principal.to_string()),
+            index: None,
+        }
+    }
+
+    #[test]
+    fn test_account_pnl_separates_interest_flows_and_price() {
+        let day = SECONDS_PER_DAY;
+        let snapshots = vec![
+            // 1000 USDC supplied at $1, earns 10 USDC and the user deposits 500 more
+            snapshot("SUPPLIER", day, "1000000000", "1000", "1000000000"),
+            snapshot("SUPPLIER", 2 * day, "1510000000", "1510", "1500000000"),
+            // 400 borrowed, accrues 4 of interest while the borrowed asset drops to $0.99
+            snapshot("BORROWER", day, "400000000", "400", "400000000"),
+            snapshot("BORROWER", 2 * day, "404000000", "399.96", "400000000"),
+        ];
+
+        let pnl = compute_account_pnl("0xaccount".to_string(), snapshots);
+
+        assert_eq!(pnl.days.len(), 2);
+        let first = &pnl.days[0];
+        assert_eq!(first.day, day);
+        assert_eq!(first.pnl_usd, 0.0);
+        assert!((first.net_balance_usd - 600.0).abs() < 1e-9);
+
+        let second = &pnl.days[1];
+        assert!((second.interest_usd - (10.0 - 4.0 * 0.99)).abs() < 1e-9);
+        assert!((second.flows_usd - 500.0).abs() < 1e-9);
+        assert!((second.price_usd - 4.0).abs() < 1e-9);
+        assert!((second.net_balance_usd - (1510.0 - 399.96)).abs() < 1e-9);
+        assert!((pnl.total_pnl_usd - (10.0 - 3.96 + 4.0)).abs() < 1e-9);
+    }
+}
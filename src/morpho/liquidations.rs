@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::{Decimal, EntityRef};
+use crate::client::GraphClient;
+use crate::numeric::to_f64;
+
+/// Number of liquidation ids sent per bad debt lookup
+const BAD_DEBT_BATCH_SIZE: usize = 500;
+
+/// Selects which liquidations [`fetch_liquidations`] returns
+#[derive(Debug, Clone, Default)]
+pub struct LiquidationFilter {
+    pub market: Option<String>,
+    pub liquidator: Option<String>,
+    pub liquidatee: Option<String>,
+    /// Inclusive lower bound on the event timestamp
+    pub from: Option<i64>,
+    /// Exclusive upper bound on the event timestamp
+    pub to: Option<i64>,
+    /// Minimum seized collateral value in USD
+    pub min_amount_usd: Option<f64>,
+    /// Maximum number of liquidations
+    pub limit: Option<usize>,
+}
+
+impl LiquidationFilter {
+    fn to_where(&self) -> Value {
+        let mut filter = Map::new();
+        if let Some(market) = &self.market {
+            filter.insert("market".to_string(), json!(market.to_lowercase()));
+        }
+        if let Some(liquidator) = &self.liquidator {
+            filter.insert("liquidator".to_string(), json!(liquidator.to_lowercase()));
+        }
+        if let Some(liquidatee) = &self.liquidatee {
+            filter.insert("liquidatee".to_string(), json!(liquidatee.to_lowercase()));
+        }
+        if let Some(from) = self.from {
+            filter.insert("timestamp_gte".to_string(), json!(from.to_string()));
+        }
+        if let Some(to) = self.to {
+            filter.insert("timestamp_lt".to_string(), json!(to.to_string()));
+        }
+        if let Some(min_amount_usd) = self.min_amount_usd {
+            filter.insert(
+                "amountUSD_gte".to_string(),
+                json!(min_amount_usd.to_string()),
+            );
+        }
+        Value::Object(filter)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidationMarket {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidationToken {
+    pub symbol: String,
+    pub decimals: i32,
+}
+
+/// Bad debt realized by a liquidation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BadDebt {
+    pub id: String,
+    #[serde(rename = "badDebt")]
+    pub bad_debt: String,
+    #[serde(rename = "badDebtUSD")]
+    pub bad_debt_usd: Option<Decimal>,
+}
+
+/// A Morpho Blue liquidation, joined with any bad debt it realized
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Liquidation {
+    pub id: String,
+    pub hash: String,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    pub timestamp: String,
+    pub liquidator: EntityRef,
+    pub liquidatee: EntityRef,
+    pub market: LiquidationMarket,
+    /// Loan token repaid by the liquidator
+    pub asset: LiquidationToken,
+    /// Collateral seized, in native units
+    pub amount: String,
+    #[serde(rename = "collateralAsset")]
+    pub collateral_asset: LiquidationToken,
+    /// Debt repaid, in native units
+    pub repaid: String,
+    #[serde(rename = "repaidUSD")]
+    pub repaid_usd: Decimal,
+    /// Collateral seized, in USD
+    #[serde(rename = "amountUSD")]
+    pub amount_usd: Decimal,
+    #[serde(rename = "profitUSD")]
+    pub profit_usd: Decimal,
+    #[serde(default, rename = "badDebt")]
+    pub bad_debt: Option<BadDebt>,
+}
+
+impl Liquidation {
+    /// Bad debt realized by this liquidation in USD, zero if none
+    pub fn bad_debt_usd(&self) -> f64 {
+        self.bad_debt
+            .as_ref()
+            .and_then(|bad_debt| bad_debt.bad_debt_usd.as_deref())
+            .map(to_f64)
+            .unwrap_or(0.0)
+    }
+}
+
+/// Liquidation totals for a market or a liquidator
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LiquidationAggregate {
+    /// Market id or liquidator address
+    pub key: String,
+    pub count: usize,
+    /// Seized collateral in USD
+    pub volume_usd: f64,
+    pub repaid_usd: f64,
+    pub profit_usd: f64,
+    pub bad_debt_usd: f64,
+    /// Number of liquidations that realized bad debt
+    pub bad_debt_count: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BadDebtRealization {
+    id: String,
+    liquidation: EntityRef,
+    #[serde(rename = "badDebt")]
+    bad_debt: String,
+    #[serde(rename = "badDebtUSD")]
+    bad_debt_usd: Option<Decimal>,
+}
+
+/// Fetch liquidations matching `filter`, newest first, joined with any realized bad debt
+pub async fn fetch_liquidations(
+    client: &GraphClient,
+    filter: &LiquidationFilter,
+) -> Result<Vec<Liquidation>> {
+    let query = r#"
+    query MorphoLiquidations($first: Int!, $lastId: Bytes!, $where: Liquidate_filter!, $orderBy: Liquidate_orderBy!, $orderDirection: OrderDirection!) {
+        liquidates(
+            first: $first,
+            orderBy: $orderBy,
+            orderDirection: $orderDirection,
+            where: { and: [{ id_gt: $lastId }, $where] }
+        ) {
+            id
+            hash
+            blockNumber
+            timestamp
+            liquidator {
+                id
+            }
+            liquidatee {
+                id
+            }
+            market {
+                id
+                name
+            }
+            asset {
+                symbol
+                decimals
+            }
+            amount
+            collateralAsset {
+                symbol
+                decimals
+            }
+            repaid
+            repaidUSD
+            amountUSD
+            profitUSD
+        }
+    }
+    "#;
+
+    let variables = json!({
+        "where": filter.to_where(),
+    });
+
+    let mut liquidations: Vec<Liquidation> = client
+        .query_newest(query, "liquidates", "timestamp", variables, filter.limit)
+        .await?;
+    liquidations.sort_by_key(|l| std::cmp::Reverse(l.timestamp.parse::<i64>().unwrap_or(0)));
+    if let Some(limit) = filter.limit {
+        liquidations.truncate(limit);
+    }
+
+    let realizations = fetch_bad_debt_realizations(client, &liquidations).await?;
+    join_bad_debts(&mut liquidations, realizations);
+    Ok(liquidations)
+}
+
+async fn fetch_bad_debt_realizations(
+    client: &GraphClient,
+    liquidations: &[Liquidation],
+) -> Result<Vec<BadDebtRealization>> {
+    let query = r#"
+    query MorphoBadDebtRealizations($first: Int!, $lastId: Bytes!, $liquidations: [String!]!) {
+        badDebtRealizations(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, liquidation_in: $liquidations }
+        ) {
+            id
+            liquidation {
+                id
+            }
+            badDebt
+            badDebtUSD
+        }
+    }
+    "#;
+
+    let mut realizations = Vec::new();
+    for batch in liquidations.chunks(BAD_DEBT_BATCH_SIZE) {
+        let ids: Vec<&str> = batch.iter().map(|l| l.id.as_str()).collect();
+        realizations.extend(
+            client
+                .query_paginated::<BadDebtRealization>(
+                    query,
+                    "badDebtRealizations",
+                    json!({ "liquidations": ids }),
+                    None,
+                )
+                .await?,
+        );
+    }
+    Ok(realizations)
+}
+
+/// Attach each bad debt realization to the liquidation that realized it
+fn join_bad_debts(liquidations: &mut [Liquidation], realizations: Vec<BadDebtRealization>) {
+    let bad_debts: HashMap<String, BadDebt> = realizations
+        .into_iter()
+        .map(|realization| {
+            (
+                realization.liquidation.id,
+                BadDebt {
+                    id: realization.id,
+                    bad_debt: realization.bad_debt,
+                    bad_debt_usd: realization.bad_debt_usd,
+                },
+            )
+        })
+        .collect();
+    for liquidation in liquidations.iter_mut() {
+        liquidation.bad_debt = bad_debts.get(&liquidation.id).cloned();
+    }
+}
+
+/// Aggregate liquidations per market, largest volume first
+pub fn aggregate_by_market(liquidations: &[Liquidation]) -> Vec<LiquidationAggregate> {
+    aggregate_by(liquidations, |l| l.market.id.as_str())
+}
+
+/// Aggregate liquidations per liquidator, largest volume first
+pub fn aggregate_by_liquidator(liquidations: &[Liquidation]) -> Vec<LiquidationAggregate> {
+    aggregate_by(liquidations, |l| l.liquidator.id.as_str())
+}
+
+fn aggregate_by<'a, F>(liquidations: &'a [Liquidation], key: F) -> Vec<LiquidationAggregate>
+where
+    F: Fn(&'a Liquidation) -> &'a str,
+{
+    let mut aggregates: HashMap<&str, LiquidationAggregate> = HashMap::new();
+    for liquidation in liquidations {
+        let key = key(liquidation);
+        let aggregate = aggregates
+            .entry(key)
+            .or_insert_with(|| LiquidationAggregate {
+                key: key.to_string(),
+                ..Default::default()
+            });
+
+        let bad_debt_usd = liquidation.bad_debt_usd();
+        aggregate.count += 1;
+        aggregate.volume_usd += to_f64(&liquidation.amount_usd);
+        aggregate.repaid_usd += to_f64(&liquidation.repaid_usd);
+        aggregate.profit_usd += to_f64(&liquidation.profit_usd);
+        aggregate.bad_debt_usd += bad_debt_usd;
+        if liquidation.bad_debt.is_some() {
+            aggregate.bad_debt_count += 1;
+        }
+    }
+
+    let mut aggregates: Vec<LiquidationAggregate> = aggregates.into_values().collect();
+    aggregates.sort_by(|a, b| b.volume_usd.total_cmp(&a.volume_usd));
+    aggregates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn liquidation(id: &str, market: &str, liquidator: &str, amount_usd: &str) -> Liquidation {
+        let token = || LiquidationToken {
+            symbol: "USDC".to_string(),
+            decimals: 6,
+        };
+        Liquidation {
+            id: id.to_string(),
+            hash: id.to_string(),
+            block_number: "1".to_string(),
+            timestamp: "1".to_string(),
+            liquidator: EntityRef {
+                id: liquidator.to_string(),
+            },
+            liquidatee: EntityRef {
+                id: "0xborrower".to_string(),
+            },
+            market: LiquidationMarket {
+                id: market.to_string(),
+                name: market.to_string(),
+            },
+            asset: token(),
+            amount: "0".to_string(),
+            collateral_asset: token(),
+            repaid: "0".to_string(),
+            repaid_usd: "90".to_string(),
+            amount_usd: amount_usd.to_string(),
+            profit_usd: "10".to_string(),
+            bad_debt: None,
+        }
+    }
+
+    #[test]
+    fn test_join_bad_debts_by_liquidation_id() {
+        let mut liquidations = vec![
+            liquidation("0x01", "0xm1", "0xbot", "100"),
+            liquidation("0x02", "0xm1", "0xbot", "100"),
+        ];
+        let realizations = vec![BadDebtRealization {
+            id: "0xbad".to_string(),
+            liquidation: EntityRef {
+                id: "0x02".to_string(),
+            },
+            bad_debt: "25000000".to_string(),
+            bad_debt_usd: Some("25".to_string()),
+        }];
+
+        join_bad_debts(&mut liquidations, realizations);
+
+        assert!(liquidations[0].bad_debt.is_none());
+        assert_eq!(liquidations[0].bad_debt_usd(), 0.0);
+        assert_eq!(liquidations[1].bad_debt_usd(), 25.0);
+    }
+
+    #[test]
+    fn test_aggregate_by_market_and_liquidator() {
+        let mut liquidations = vec![
+            liquidation("0x01", "0xm1", "0xbot", "100"),
+            liquidation("0x02", "0xm2", "0xbot", "300"),
+            liquidation("0x03", "0xm1", "0xother", "50"),
+        ];
+        liquidations[2].bad_debt = Some(BadDebt {
+            id: "0xbad".to_string(),
+            bad_debt: "5".to_string(),
+            bad_debt_usd: Some("5".to_string()),
+        });
+
+        let by_market = aggregate_by_market(&liquidations);
+        assert_eq!(by_market.len(), 2);
+        assert_eq!(by_market[0].key, "0xm2");
+        assert_eq!(by_market[1].key, "0xm1");
+        assert_eq!(by_market[1].count, 2);
+        assert_eq!(by_market[1].volume_usd, 150.0);
+        assert_eq!(by_market[1].profit_usd, 20.0);
+        assert_eq!(by_market[1].bad_debt_usd, 5.0);
+        assert_eq!(by_market[1].bad_debt_count, 1);
+
+        let by_liquidator = aggregate_by_liquidator(&liquidations);
+        assert_eq!(by_liquidator[0].key, "0xbot");
+        assert_eq!(by_liquidator[0].volume_usd, 400.0);
+        assert_eq!(by_liquidator[0].repaid_usd, 180.0);
+    }
+}
@@ -5,8 +5,52 @@ use serde_json::json;
 use crate::client::GraphClient;
 
 // Create a simple module for scalar types
+#[allow(dead_code)]
 mod scalars;
 
+pub mod events;
+pub mod governance;
+mod history;
+mod liquidations;
+pub mod oracles;
+mod portfolio;
+mod protocol;
+pub mod public_allocator;
+mod rates;
+mod revenue;
+pub mod risk;
+mod snapshots;
+pub mod vault_concentration;
+pub mod vault_performance;
+pub mod vaults;
+
+pub use history::{
+    compute_account_pnl, fetch_account_pnl, fetch_position_history, AccountPnl, DailyPnl,
+    PositionSnapshot, SnapshotAsset, SnapshotPosition,
+};
+pub use liquidations::{
+    aggregate_by_liquidator, aggregate_by_market, fetch_liquidations, BadDebt, Liquidation,
+    LiquidationAggregate, LiquidationFilter, LiquidationMarket, LiquidationToken,
+};
+pub use portfolio::{
+    fetch_account_portfolio, AccountPortfolio, MarketPortfolio, Position, PositionAsset,
+    PositionMarket, VaultHolding,
+};
+pub use protocol::{
+    fetch_financials_daily_snapshots, fetch_protocol_overview, fetch_usage_daily_snapshots,
+    fetch_usage_hourly_snapshots, FinancialsDailySnapshot, ProtocolOverview,
+    UsageMetricsDailySnapshot, UsageMetricsHourlySnapshot,
+};
+pub use rates::{fetch_market_rates, MarketFilter, MarketRates};
+pub use revenue::{
+    fetch_market_revenue, rank_by_protocol_revenue, Fee, MarketRevenue, RevenueDetail,
+    SourceRevenue,
+};
+pub use snapshots::{
+    fetch_market_daily_snapshots, fetch_market_hourly_snapshots, MarketDailySnapshot,
+    MarketHourlySnapshot,
+};
+
 // Define simple types for responses
 pub type Decimal = String;
 
@@ -58,6 +102,16 @@ pub struct Rate {
     pub market: MarketRef,
 }
 
+/// An interest rate attached to a market or one of its snapshots, in percent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterestRate {
+    pub id: String,
+    pub rate: String,
+    pub side: String,
+    #[serde(rename = "type")]
+    pub rate_type: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MarketRef {
     pub id: String,
@@ -71,6 +125,12 @@ pub struct TokenRef {
     pub symbol: String,
 }
 
+/// Reference to another entity, such as an account or a market, by id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityRef {
+    pub id: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MarketsResponse {
     pub markets: Vec<Market>,
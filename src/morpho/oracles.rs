@@ -0,0 +1,371 @@
+//! Morpho market oracles and Chainlink feed inspection with staleness detection.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{Decimal, GraphQLResponse, MarketFilter};
+use crate::client::GraphClient;
+
+/// Heartbeat assumed for feeds without an explicit entry, matching Chainlink's most common 24h
+const DEFAULT_HEARTBEAT_SECONDS: i64 = 24 * 60 * 60;
+
+/// Oracle pricing a Morpho market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Oracle {
+    pub id: String,
+    #[serde(rename = "oracleAddress")]
+    pub oracle_address: String,
+    #[serde(rename = "blockCreated")]
+    pub block_created: String,
+    #[serde(rename = "timestampCreated")]
+    pub timestamp_created: String,
+    #[serde(rename = "isActive")]
+    pub is_active: bool,
+    #[serde(rename = "isUSD")]
+    pub is_usd: bool,
+    /// Hash of the transaction in which the oracle stopped being used
+    #[serde(rename = "hashEnded")]
+    pub hash_ended: Option<String>,
+    #[serde(rename = "oracleSource")]
+    pub oracle_source: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainlinkAggregator {
+    pub id: String,
+    #[serde(rename = "isDeprecated")]
+    pub is_deprecated: bool,
+}
+
+/// A Chainlink proxy tracked by the subgraph, with its latest answer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainlinkFeed {
+    pub id: String,
+    pub proxy: String,
+    #[serde(rename = "isUSD")]
+    pub is_usd: bool,
+    #[serde(rename = "currentAggregator")]
+    pub current_aggregator: ChainlinkAggregator,
+    #[serde(rename = "lastPrice")]
+    pub last_price: String,
+    pub decimals: i32,
+    #[serde(rename = "lastPriceBD")]
+    pub last_price_bd: Decimal,
+    #[serde(rename = "lastPriceUpdateTimestamp")]
+    pub last_price_update_timestamp: String,
+    #[serde(rename = "lastPriceUpdateBlock")]
+    pub last_price_update_block: String,
+}
+
+impl ChainlinkFeed {
+    /// Seconds since the feed last updated, at `now`
+    pub fn age(&self, now: i64) -> i64 {
+        now - self.last_price_update_timestamp.parse::<i64>().unwrap_or(0)
+    }
+}
+
+/// Expected update interval of each Chainlink feed, keyed by proxy address
+#[derive(Debug, Clone)]
+pub struct HeartbeatConfig {
+    pub default_seconds: i64,
+    pub per_feed: HashMap<String, i64>,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        HeartbeatConfig {
+            default_seconds: DEFAULT_HEARTBEAT_SECONDS,
+            per_feed: HashMap::new(),
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    /// Set the heartbeat of a single feed
+    pub fn with_feed(mut self, proxy: &str, seconds: i64) -> Self {
+        self.per_feed.insert(proxy.to_lowercase(), seconds);
+        self
+    }
+
+    pub fn heartbeat(&self, proxy: &str) -> i64 {
+        self.per_feed
+            .get(&proxy.to_lowercase())
+            .copied()
+            .unwrap_or(self.default_seconds)
+    }
+}
+
+/// Problem detected on a market's oracle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OracleFlag {
+    /// The feed has not updated within its heartbeat
+    Stale {
+        age_seconds: i64,
+        heartbeat_seconds: i64,
+    },
+    /// The oracle is no longer the market's source of truth
+    Inactive,
+    /// The oracle was replaced in the given transaction
+    Replaced { hash_ended: String },
+    /// The feed's current aggregator has been deprecated by Chainlink
+    DeprecatedAggregator { aggregator: String },
+    /// No tracked Chainlink proxy matches the oracle address, so staleness and aggregator
+    /// deprecation could not be checked. This is the case for wrapper oracles such as
+    /// `MorphoChainlinkOracleV2`, whose underlying feeds the subgraph does not index.
+    NoTrackedFeed,
+}
+
+/// A market's oracle with its Chainlink feed, when one is tracked, and detected issues
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketOracle {
+    pub market_id: String,
+    pub market_name: String,
+    pub oracle: Oracle,
+    pub feed: Option<ChainlinkFeed>,
+    /// Last price update of the feed, if one is tracked
+    pub last_update_timestamp: Option<i64>,
+    pub flags: Vec<OracleFlag>,
+}
+
+impl MarketOracle {
+    /// Whether no issue was detected. A market without a tracked feed is never healthy, as
+    /// its price updates were not checked.
+    pub fn is_healthy(&self) -> bool {
+        self.flags.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OracleMarket {
+    id: String,
+    name: String,
+    oracle: Oracle,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OracleMarketsResponse {
+    markets: Vec<OracleMarket>,
+}
+
+/// Fetch every Chainlink proxy tracked by the subgraph
+pub async fn fetch_chainlink_feeds(client: &GraphClient) -> Result<Vec<ChainlinkFeed>> {
+    let query = r#"
+    query MorphoChainlinkFeeds($first: Int!, $lastId: Bytes!) {
+        _chainlinkProxies(first: $first, orderBy: id, orderDirection: asc, where: { id_gt: $lastId }) {
+            id
+            proxy
+            isUSD
+            currentAggregator {
+                id
+                isDeprecated
+            }
+            lastPrice
+            decimals
+            lastPriceBD
+            lastPriceUpdateTimestamp
+            lastPriceUpdateBlock
+        }
+    }
+    "#;
+
+    client
+        .query_paginated(query, "_chainlinkProxies", json!({}), None)
+        .await
+}
+
+/// Fetch the oracle of each market matching `filter`, joined with its Chainlink feed and
+/// flagged for staleness, inactivity, replacement, deprecated aggregators or a missing feed
+pub async fn fetch_market_oracles(
+    client: &GraphClient,
+    filter: &MarketFilter,
+    heartbeats: &HeartbeatConfig,
+) -> Result<Vec<MarketOracle>> {
+    let query = r#"
+    query MorphoMarketOracles($first: Int, $where: Market_filter) {
+        markets(first: $first, orderBy: totalValueLockedUSD, orderDirection: desc, where: $where) {
+            id
+            name
+            oracle {
+                id
+                oracleAddress
+                blockCreated
+                timestampCreated
+                isActive
+                isUSD
+                hashEnded
+                oracleSource
+            }
+        }
+    }
+    "#;
+
+    let variables = json!({
+        "first": filter.first(),
+        "where": filter.to_where(),
+    });
+
+    let response: GraphQLResponse<OracleMarketsResponse> =
+        client.query_raw(query, variables).await?;
+    let feeds = fetch_chainlink_feeds(client).await?;
+    let now = chrono::Utc::now().timestamp();
+
+    Ok(response
+        .data
+        .markets
+        .into_iter()
+        .map(|market| assess_oracle(market, &feeds, heartbeats, now))
+        .collect())
+}
+
+fn assess_oracle(
+    market: OracleMarket,
+    feeds: &[ChainlinkFeed],
+    heartbeats: &HeartbeatConfig,
+    now: i64,
+) -> MarketOracle {
+    let address = market.oracle.oracle_address.to_lowercase();
+    let feed = feeds
+        .iter()
+        .find(|feed| feed.proxy.to_lowercase() == address || feed.id.to_lowercase() == address)
+        .cloned();
+
+    let mut flags = Vec::new();
+    if !market.oracle.is_active {
+        flags.push(OracleFlag::Inactive);
+    }
+    if let Some(hash_ended) = &market.oracle.hash_ended {
+        flags.push(OracleFlag::Replaced {
+            hash_ended: hash_ended.clone(),
+        });
+    }
+    match &feed {
+        Some(feed) => {
+            let heartbeat = heartbeats.heartbeat(&feed.proxy);
+            let age = feed.age(now);
+            if age > heartbeat {
+                flags.push(OracleFlag::Stale {
+                    age_seconds: age,
+                    heartbeat_seconds: heartbeat,
+                });
+            }
+            if feed.current_aggregator.is_deprecated {
+                flags.push(OracleFlag::DeprecatedAggregator {
+                    aggregator: feed.current_aggregator.id.clone(),
+                });
+            }
+        }
+        None => flags.push(OracleFlag::NoTrackedFeed),
+    }
+
+    MarketOracle {
+        market_id: market.id,
+        market_name: market.name,
+        last_update_timestamp: feed
+            .as_ref()
+            .and_then(|feed| feed.last_price_update_timestamp.parse().ok()),
+        oracle: market.oracle,
+        feed,
+        flags,
+    }
+}
+
+/// Return the feeds that have not updated within their heartbeat at `now`
+pub fn stale_feeds<'a>(
+    feeds: &'a [ChainlinkFeed],
+    heartbeats: &HeartbeatConfig,
+    now: i64,
+) -> Vec<&'a ChainlinkFeed> {
+    feeds
+        .iter()
+        .filter(|feed| feed.age(now) > heartbeats.heartbeat(&feed.proxy))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(proxy: &str, updated_at: i64) -> ChainlinkFeed {
+        ChainlinkFeed {
+            id: proxy.to_string(),
+            proxy: proxy.to_string(),
+            is_usd: true,
+            current_aggregator: ChainlinkAggregator {
+                id: "0xaggregator".to_string(),
+                is_deprecated: false,
+            },
+            last_price: "200000000000".to_string(),
+            decimals: 8,
+            last_price_bd: "2000".to_string(),
+            last_price_update_timestamp: updated_at.to_string(),
+            last_price_update_block: "1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_stale_feeds_respect_per_feed_heartbeat() {
+        let now = 100_000;
+        let feeds = vec![feed("0xslow", now - 7200), feed("0xfast", now - 7200)];
+        let heartbeats = HeartbeatConfig::default().with_feed("0xFAST", 3600);
+
+        let stale = stale_feeds(&feeds, &heartbeats, now);
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].proxy, "0xfast");
+    }
+
+    #[test]
+    fn test_replaced_oracle_is_flagged() {
+        let market = OracleMarket {
+            id: "0xmarket".to_string(),
+            name: "WETH/USDC".to_string(),
+            oracle: Oracle {
+                id: "0xoracle".to_string(),
+                oracle_address: "0xfeed".to_string(),
+                block_created: "1".to_string(),
+                timestamp_created: "1".to_string(),
+                is_active: false,
+                is_usd: true,
+                hash_ended: Some("0xhash".to_string()),
+                oracle_source: Some("CHAINLINK".to_string()),
+            },
+        };
+
+        let oracle = assess_oracle(
+            market,
+            &[feed("0xfeed", 0)],
+            &HeartbeatConfig::default(),
+            100,
+        );
+        assert_eq!(
+            oracle.flags,
+            vec![
+                OracleFlag::Inactive,
+                OracleFlag::Replaced {
+                    hash_ended: "0xhash".to_string()
+                },
+            ]
+        );
+        assert_eq!(oracle.last_update_timestamp, Some(0));
+
+        let wrapper = assess_oracle(
+            OracleMarket {
+                oracle: Oracle {
+                    oracle_address: "0xwrapper".to_string(),
+                    is_active: true,
+                    hash_ended: None,
+                    ..oracle.oracle
+                },
+                id: oracle.market_id,
+                name: oracle.market_name,
+            },
+            &[feed("0xfeed", 0)],
+            &HeartbeatConfig::default(),
+            100,
+        );
+        assert_eq!(wrapper.flags, vec![OracleFlag::NoTrackedFeed]);
+        assert!(!wrapper.is_healthy());
+    }
+}
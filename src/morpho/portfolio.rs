@@ -0,0 +1,388 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{Decimal, EntityRef};
+use crate::client::GraphClient;
+use crate::numeric::to_f64;
+
+/// Token held in a position, with the price used to value it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionAsset {
+    pub id: String,
+    pub symbol: String,
+    pub decimals: i32,
+    #[serde(rename = "lastPriceUSD")]
+    pub last_price_usd: Option<Decimal>,
+}
+
+/// Market fields selected alongside a position
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionMarket {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "inputToken")]
+    pub input_token: PositionAsset,
+    #[serde(rename = "inputTokenPriceUSD")]
+    pub input_token_price_usd: Decimal,
+    #[serde(rename = "maximumLTV")]
+    pub maximum_ltv: Decimal,
+    #[serde(rename = "liquidationThreshold")]
+    pub liquidation_threshold: Decimal,
+}
+
+/// A Morpho Blue position
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Position {
+    pub id: String,
+    pub account: EntityRef,
+    pub market: PositionMarket,
+    pub asset: PositionAsset,
+    /// `COLLATERAL`, `SUPPLIER` or `BORROWER`
+    pub side: String,
+    #[serde(rename = "isCollateral")]
+    pub is_collateral: Option<bool>,
+    pub balance: String,
+    pub principal: Option<String>,
+    pub shares: Option<String>,
+    #[serde(rename = "timestampOpened")]
+    pub timestamp_opened: String,
+    #[serde(rename = "blockNumberOpened")]
+    pub block_number_opened: String,
+}
+
+impl Position {
+    /// Balance in whole tokens
+    pub fn amount(&self) -> f64 {
+        to_f64(&self.balance) / 10f64.powi(self.asset.decimals)
+    }
+
+    /// Price of the position's asset in USD, using the market input token price when it applies
+    pub fn price_usd(&self) -> f64 {
+        if self.asset.id == self.market.input_token.id {
+            to_f64(&self.market.input_token_price_usd)
+        } else {
+            self.asset
+                .last_price_usd
+                .as_deref()
+                .map(to_f64)
+                .unwrap_or(0.0)
+        }
+    }
+
+    /// Balance valued in USD
+    pub fn balance_usd(&self) -> f64 {
+        self.amount() * self.price_usd()
+    }
+}
+
+/// Fields selected for every Morpho Blue position
+pub(crate) const POSITION_FIELDS: &str = r#"
+    fragment PositionFields on Position {
+        id
+        account {
+            id
+        }
+        market {
+            id
+            name
+            inputToken {
+                id
+                symbol
+                decimals
+                lastPriceUSD
+            }
+            inputTokenPriceUSD
+            maximumLTV
+            liquidationThreshold
+        }
+        asset {
+            id
+            symbol
+            decimals
+            lastPriceUSD
+        }
+        side
+        isCollateral
+        balance
+        principal
+        shares
+        timestampOpened
+        blockNumberOpened
+    }
+"#;
+
+/// An account's open positions in one market, split by side
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketPortfolio {
+    pub market_id: String,
+    pub market_name: String,
+    pub collateral: Vec<Position>,
+    pub supplied: Vec<Position>,
+    pub borrowed: Vec<Position>,
+    pub collateral_usd: f64,
+    pub supplied_usd: f64,
+    pub borrowed_usd: f64,
+}
+
+/// Shares held in a MetaMorpho vault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultHolding {
+    pub vault_id: String,
+    pub vault_name: String,
+    pub asset_symbol: String,
+    pub shares: String,
+    /// Assets the shares were worth at the last update, in native units
+    pub assets: String,
+    pub assets_usd: Option<f64>,
+}
+
+/// All open Morpho Blue positions and MetaMorpho holdings of an account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountPortfolio {
+    pub address: String,
+    pub markets: Vec<MarketPortfolio>,
+    pub vaults: Vec<VaultHolding>,
+    pub total_collateral_usd: f64,
+    pub total_supplied_usd: f64,
+    pub total_borrowed_usd: f64,
+    pub total_vaults_usd: f64,
+    /// Collateral, supply and vault holdings minus debt
+    pub net_usd: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct VaultAsset {
+    symbol: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct HeldVault {
+    id: String,
+    name: String,
+    asset: VaultAsset,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MetaMorphoPosition {
+    shares: String,
+    #[serde(rename = "lastAssetsBalance")]
+    last_assets_balance: String,
+    #[serde(rename = "lastAssetsBalanceUSD")]
+    last_assets_balance_usd: Option<Decimal>,
+    #[serde(rename = "metaMorpho")]
+    meta_morpho: HeldVault,
+}
+
+#[derive(Debug, Clone)]
+struct PortfolioAccount {
+    positions: Vec<Position>,
+    meta_morpho_positions: Vec<MetaMorphoPosition>,
+}
+
+/// Fetch all open Morpho Blue positions and MetaMorpho holdings of an account
+pub async fn fetch_account_portfolio(
+    client: &GraphClient,
+    address: &str,
+) -> Result<AccountPortfolio> {
+    let positions_query = format!(
+        r#"
+    query MorphoAccountPositions($first: Int!, $lastId: ID!, $account: String!) {{
+        positions(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: {{ id_gt: $lastId, account: $account, balance_gt: "0" }}
+        ) {{
+            ...PositionFields
+        }}
+    }}
+    {}"#,
+        POSITION_FIELDS
+    );
+    let vaults_query = r#"
+    query MorphoAccountVaultPositions($first: Int!, $lastId: Bytes!, $account: String!) {
+        metaMorphoPositions(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, account: $account, shares_gt: "0" }
+        ) {
+            id
+            shares
+            lastAssetsBalance
+            lastAssetsBalanceUSD
+            metaMorpho {
+                id
+                name
+                asset {
+                    symbol
+                }
+            }
+        }
+    }
+    "#;
+
+    let address = address.to_lowercase();
+    let variables = json!({ "account": address });
+    let account = PortfolioAccount {
+        positions: client
+            .query_paginated(&positions_query, "positions", variables.clone(), None)
+            .await?,
+        meta_morpho_positions: client
+            .query_paginated(vaults_query, "metaMorphoPositions", variables, None)
+            .await?,
+    };
+
+    Ok(build_portfolio(address, account))
+}
+
+fn build_portfolio(address: String, account: PortfolioAccount) -> AccountPortfolio {
+    let mut markets: Vec<MarketPortfolio> = Vec::new();
+    for position in account.positions {
+        let index = match markets
+            .iter()
+            .position(|m| m.market_id == position.market.id)
+        {
+            Some(index) => index,
+            None => {
+                markets.push(MarketPortfolio {
+                    market_id: position.market.id.clone(),
+                    market_name: position.market.name.clone(),
+                    collateral: Vec::new(),
+                    supplied: Vec::new(),
+                    borrowed: Vec::new(),
+                    collateral_usd: 0.0,
+                    supplied_usd: 0.0,
+                    borrowed_usd: 0.0,
+                });
+                markets.len() - 1
+            }
+        };
+
+        let market = &mut markets[index];
+        let value = position.balance_usd();
+        match position.side.as_str() {
+            "BORROWER" => {
+                market.borrowed_usd += value;
+                market.borrowed.push(position);
+            }
+            "COLLATERAL" => {
+                market.collateral_usd += value;
+                market.collateral.push(position);
+            }
+            _ => {
+                market.supplied_usd += value;
+                market.supplied.push(position);
+            }
+        }
+    }
+    markets.sort_by(|a, b| {
+        let size = |m: &MarketPortfolio| m.collateral_usd + m.supplied_usd + m.borrowed_usd;
+        size(b).total_cmp(&size(a))
+    });
+
+    let vaults: Vec<VaultHolding> = account
+        .meta_morpho_positions
+        .into_iter()
+        .map(|position| VaultHolding {
+            vault_id: position.meta_morpho.id,
+            vault_name: position.meta_morpho.name,
+            asset_symbol: position.meta_morpho.asset.symbol,
+            shares: position.shares,
+            assets: position.last_assets_balance,
+            assets_usd: position.last_assets_balance_usd.as_deref().map(to_f64),
+        })
+        .collect();
+
+    let total_collateral_usd = markets.iter().map(|m| m.collateral_usd).sum();
+    let total_supplied_usd = markets.iter().map(|m| m.supplied_usd).sum();
+    let total_borrowed_usd = markets.iter().map(|m| m.borrowed_usd).sum();
+    let total_vaults_usd = vaults.iter().filter_map(|v| v.assets_usd).sum();
+
+    AccountPortfolio {
+        address,
+        markets,
+        vaults,
+        total_collateral_usd,
+        total_supplied_usd,
+        total_borrowed_usd,
+        total_vaults_usd,
+        net_usd: total_collateral_usd + total_supplied_usd + total_vaults_usd - total_borrowed_usd,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(id: &str, price: &str) -> PositionAsset {
+        PositionAsset {
+            id: id.to_string(),
+            symbol: id.to_string(),
+            decimals: 0,
+            last_price_usd: Some(price.to_string()),
+        }
+    }
+
+    fn position(market: &str, side: &str, token: &str, balance: &str) -> Position {
+        Position {
+            id: format!("0xaccount-{}-{}", market, side),
+            account: EntityRef {
+                id: "0xaccount".to_string(),
+            },
+            market: PositionMarket {
+                id: market.to_string(),
+                name: market.to_string(),
+                input_token: asset("usdc", "1"),
+                input_token_price_usd: "1".to_string(),
+                maximum_ltv: "86".to_string(),
+                liquidation_threshold: "86".to_string(),
+            },
+            asset: asset(token, "2000"),
+            side: side.to_string(),
+            is_collateral: None,
+            balance: balance.to_string(),
+            principal: None,
+            shares: None,
+            timestamp_opened: "1".to_string(),
+            block_number_opened: "1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_build_portfolio_groups_sides_and_nets_debt() {
+        let account = PortfolioAccount {
+            positions: vec![
+                position("0xsmall", "SUPPLIER", "usdc", "100"),
+                position("0xlarge", "COLLATERAL", "weth", "2"),
+                position("0xlarge", "BORROWER", "usdc", "1500"),
+            ],
+            meta_morpho_positions: vec![MetaMorphoPosition {
+                shares: "10".to_string(),
+                last_assets_balance: "10".to_string(),
+                last_assets_balance_usd: Some("10".to_string()),
+                meta_morpho: HeldVault {
+                    id: "0xvault".to_string(),
+                    name: "Vault".to_string(),
+                    asset: VaultAsset {
+                        symbol: "USDC".to_string(),
+                    },
+                },
+            }],
+        };
+
+        let portfolio = build_portfolio("0xaccount".to_string(), account);
+
+        assert_eq!(portfolio.markets.len(), 2);
+        let large = &portfolio.markets[0];
+        assert_eq!(large.market_id, "0xlarge");
+        assert_eq!(large.collateral.len(), 1);
+        assert_eq!(large.borrowed.len(), 1);
+        assert_eq!(large.collateral_usd, 4000.0);
+        assert_eq!(large.borrowed_usd, 1500.0);
+        assert_eq!(portfolio.markets[1].supplied_usd, 100.0);
+        assert_eq!(portfolio.total_vaults_usd, 10.0);
+        assert_eq!(portfolio.net_usd, 4000.0 + 100.0 + 10.0 - 1500.0);
+    }
+}
@@ -0,0 +1,427 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{Decimal, Fee, GraphQLResponse, RevenueDetail};
+use crate::client::GraphClient;
+use crate::numeric::{ratio, to_f64};
+
+/// Scale of onchain LLTVs, 1e18 is 100%
+const WAD: f64 = 1e18;
+
+/// Protocol-wide totals and configuration of Morpho Blue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolOverview {
+    pub id: String,
+    pub name: String,
+    pub slug: String,
+    pub network: String,
+    #[serde(rename = "totalValueLockedUSD")]
+    pub total_value_locked_usd: Decimal,
+    #[serde(rename = "totalDepositBalanceUSD")]
+    pub total_deposit_balance_usd: Decimal,
+    #[serde(rename = "totalBorrowBalanceUSD")]
+    pub total_borrow_balance_usd: Decimal,
+    #[serde(rename = "cumulativeSupplySideRevenueUSD")]
+    pub cumulative_supply_side_revenue_usd: Decimal,
+    #[serde(rename = "cumulativeProtocolSideRevenueUSD")]
+    pub cumulative_protocol_side_revenue_usd: Decimal,
+    #[serde(rename = "cumulativeTotalRevenueUSD")]
+    pub cumulative_total_revenue_usd: Decimal,
+    #[serde(default)]
+    pub fees: Option<Vec<Fee>>,
+    /// Cumulative revenue per fee source
+    #[serde(default, rename = "revenueDetail")]
+    pub revenue_detail: Option<RevenueDetail>,
+    #[serde(rename = "cumulativeUniqueUsers")]
+    pub cumulative_unique_users: i32,
+    #[serde(rename = "cumulativeUniqueDepositors")]
+    pub cumulative_unique_depositors: i32,
+    #[serde(rename = "cumulativeUniqueBorrowers")]
+    pub cumulative_unique_borrowers: i32,
+    #[serde(rename = "totalPoolCount")]
+    pub total_pool_count: i32,
+    #[serde(rename = "openPositionCount")]
+    pub open_position_count: i32,
+    #[serde(rename = "transactionCount")]
+    pub transaction_count: i32,
+    pub owner: String,
+    #[serde(rename = "feeRecipient")]
+    pub fee_recipient: String,
+    /// Interest rate models that markets may be created with
+    #[serde(rename = "irmEnabled")]
+    pub irm_enabled: Vec<String>,
+    /// Liquidation LTVs that markets may be created with, in WAD
+    #[serde(rename = "lltvEnabled")]
+    pub lltv_enabled: Vec<String>,
+}
+
+impl ProtocolOverview {
+    /// Share of deposits that is currently borrowed, between 0 and 1
+    pub fn utilization(&self) -> f64 {
+        ratio(
+            to_f64(&self.total_borrow_balance_usd),
+            to_f64(&self.total_deposit_balance_usd),
+        )
+    }
+
+    /// Enabled LLTVs as fractions between 0 and 1, ascending
+    pub fn lltvs(&self) -> Vec<f64> {
+        let mut lltvs: Vec<f64> = self
+            .lltv_enabled
+            .iter()
+            .map(|lltv| to_f64(lltv) / WAD)
+            .collect();
+        lltvs.sort_by(f64::total_cmp);
+        lltvs
+    }
+}
+
+/// Daily protocol-wide financials
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FinancialsDailySnapshot {
+    pub id: String,
+    pub days: i32,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    pub timestamp: String,
+    #[serde(rename = "totalValueLockedUSD")]
+    pub total_value_locked_usd: Decimal,
+    #[serde(rename = "totalDepositBalanceUSD")]
+    pub total_deposit_balance_usd: Decimal,
+    #[serde(rename = "totalBorrowBalanceUSD")]
+    pub total_borrow_balance_usd: Decimal,
+    #[serde(rename = "dailySupplySideRevenueUSD")]
+    pub daily_supply_side_revenue_usd: Decimal,
+    #[serde(rename = "cumulativeSupplySideRevenueUSD")]
+    pub cumulative_supply_side_revenue_usd: Decimal,
+    #[serde(rename = "dailyProtocolSideRevenueUSD")]
+    pub daily_protocol_side_revenue_usd: Decimal,
+    #[serde(rename = "cumulativeProtocolSideRevenueUSD")]
+    pub cumulative_protocol_side_revenue_usd: Decimal,
+    #[serde(rename = "dailyTotalRevenueUSD")]
+    pub daily_total_revenue_usd: Decimal,
+    #[serde(rename = "cumulativeTotalRevenueUSD")]
+    pub cumulative_total_revenue_usd: Decimal,
+    #[serde(rename = "dailyDepositUSD")]
+    pub daily_deposit_usd: Decimal,
+    #[serde(rename = "dailyWithdrawUSD")]
+    pub daily_withdraw_usd: Decimal,
+    #[serde(rename = "dailyBorrowUSD")]
+    pub daily_borrow_usd: Decimal,
+    #[serde(rename = "dailyRepayUSD")]
+    pub daily_repay_usd: Decimal,
+    #[serde(rename = "dailyLiquidateUSD")]
+    pub daily_liquidate_usd: Decimal,
+    #[serde(rename = "dailyTransferUSD")]
+    pub daily_transfer_usd: Decimal,
+    #[serde(rename = "dailyFlashloanUSD")]
+    pub daily_flashloan_usd: Decimal,
+}
+
+/// Daily protocol-wide usage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageMetricsDailySnapshot {
+    pub id: String,
+    pub days: i32,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    pub timestamp: String,
+    #[serde(rename = "dailyActiveUsers")]
+    pub daily_active_users: i32,
+    #[serde(rename = "cumulativeUniqueUsers")]
+    pub cumulative_unique_users: i32,
+    #[serde(rename = "dailyActiveDepositors")]
+    pub daily_active_depositors: i32,
+    #[serde(rename = "dailyActiveBorrowers")]
+    pub daily_active_borrowers: i32,
+    #[serde(rename = "dailyActiveLiquidators")]
+    pub daily_active_liquidators: i32,
+    #[serde(rename = "dailyActiveLiquidatees")]
+    pub daily_active_liquidatees: i32,
+    #[serde(rename = "dailyTransactionCount")]
+    pub daily_transaction_count: i32,
+    #[serde(rename = "dailyDepositCount")]
+    pub daily_deposit_count: i32,
+    #[serde(rename = "dailyWithdrawCount")]
+    pub daily_withdraw_count: i32,
+    #[serde(rename = "dailyBorrowCount")]
+    pub daily_borrow_count: i32,
+    #[serde(rename = "dailyRepayCount")]
+    pub daily_repay_count: i32,
+    #[serde(rename = "dailyLiquidateCount")]
+    pub daily_liquidate_count: i32,
+    #[serde(rename = "dailyTransferCount")]
+    pub daily_transfer_count: i32,
+    #[serde(rename = "dailyFlashloanCount")]
+    pub daily_flashloan_count: i32,
+    #[serde(rename = "openPositionCount")]
+    pub open_position_count: i32,
+    #[serde(rename = "dailyActivePositions")]
+    pub daily_active_positions: i32,
+    #[serde(rename = "totalPoolCount")]
+    pub total_pool_count: i32,
+}
+
+/// Hourly protocol-wide usage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageMetricsHourlySnapshot {
+    pub id: String,
+    pub hours: i32,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    pub timestamp: String,
+    #[serde(rename = "hourlyActiveUsers")]
+    pub hourly_active_users: i32,
+    #[serde(rename = "cumulativeUniqueUsers")]
+    pub cumulative_unique_users: i32,
+    #[serde(rename = "hourlyTransactionCount")]
+    pub hourly_transaction_count: i32,
+    #[serde(rename = "hourlyDepositCount")]
+    pub hourly_deposit_count: i32,
+    #[serde(rename = "hourlyWithdrawCount")]
+    pub hourly_withdraw_count: i32,
+    #[serde(rename = "hourlyBorrowCount")]
+    pub hourly_borrow_count: i32,
+    #[serde(rename = "hourlyRepayCount")]
+    pub hourly_repay_count: i32,
+    #[serde(rename = "hourlyLiquidateCount")]
+    pub hourly_liquidate_count: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProtocolsResponse {
+    #[serde(rename = "lendingProtocols")]
+    lending_protocols: Vec<ProtocolOverview>,
+}
+
+/// Fetch protocol-wide totals, enabled IRMs and enabled LLTVs
+pub async fn fetch_protocol_overview(client: &GraphClient) -> Result<ProtocolOverview> {
+    let query = r#"
+    query MorphoProtocolOverview {
+        lendingProtocols(first: 1) {
+            id
+            name
+            slug
+            network
+            totalValueLockedUSD
+            totalDepositBalanceUSD
+            totalBorrowBalanceUSD
+            cumulativeSupplySideRevenueUSD
+            cumulativeProtocolSideRevenueUSD
+            cumulativeTotalRevenueUSD
+            fees {
+                id
+                rate
+                type
+            }
+            revenueDetail {
+                id
+                sources {
+                    id
+                    rate
+                    type
+                }
+                amountsUSD
+            }
+            cumulativeUniqueUsers
+            cumulativeUniqueDepositors
+            cumulativeUniqueBorrowers
+            totalPoolCount
+            openPositionCount
+            transactionCount
+            owner
+            feeRecipient
+            irmEnabled
+            lltvEnabled
+        }
+    }
+    "#;
+
+    let response: GraphQLResponse<ProtocolsResponse> = client.query_raw(query, json!({})).await?;
+    response
+        .data
+        .lending_protocols
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Morpho subgraph returned no lending protocol"))
+}
+
+/// Fetch the daily financials snapshots with a timestamp in `[from, to)`, oldest first
+pub async fn fetch_financials_daily_snapshots(
+    client: &GraphClient,
+    from: i64,
+    to: i64,
+) -> Result<Vec<FinancialsDailySnapshot>> {
+    let query = r#"
+    query MorphoFinancialsDailySnapshots($first: Int!, $lastId: Bytes!, $from: BigInt!, $to: BigInt!) {
+        financialsDailySnapshots(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, timestamp_gte: $from, timestamp_lt: $to }
+        ) {
+            id
+            days
+            blockNumber
+            timestamp
+            totalValueLockedUSD
+            totalDepositBalanceUSD
+            totalBorrowBalanceUSD
+            dailySupplySideRevenueUSD
+            cumulativeSupplySideRevenueUSD
+            dailyProtocolSideRevenueUSD
+            cumulativeProtocolSideRevenueUSD
+            dailyTotalRevenueUSD
+            cumulativeTotalRevenueUSD
+            dailyDepositUSD
+            dailyWithdrawUSD
+            dailyBorrowUSD
+            dailyRepayUSD
+            dailyLiquidateUSD
+            dailyTransferUSD
+            dailyFlashloanUSD
+        }
+    }
+    "#;
+
+    let variables = json!({
+        "from": from.to_string(),
+        "to": to.to_string(),
+    });
+
+    let mut snapshots: Vec<FinancialsDailySnapshot> = client
+        .query_paginated(query, "financialsDailySnapshots", variables, None)
+        .await?;
+    snapshots.sort_by_key(|snapshot| snapshot.days);
+    Ok(snapshots)
+}
+
+/// Fetch the daily usage snapshots with a timestamp in `[from, to)`, oldest first
+pub async fn fetch_usage_daily_snapshots(
+    client: &GraphClient,
+    from: i64,
+    to: i64,
+) -> Result<Vec<UsageMetricsDailySnapshot>> {
+    let query = r#"
+    query MorphoUsageMetricsDailySnapshots($first: Int!, $lastId: Bytes!, $from: BigInt!, $to: BigInt!) {
+        usageMetricsDailySnapshots(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, timestamp_gte: $from, timestamp_lt: $to }
+        ) {
+            id
+            days
+            blockNumber
+            timestamp
+            dailyActiveUsers
+            cumulativeUniqueUsers
+            dailyActiveDepositors
+            dailyActiveBorrowers
+            dailyActiveLiquidators
+            dailyActiveLiquidatees
+            dailyTransactionCount
+            dailyDepositCount
+            dailyWithdrawCount
+            dailyBorrowCount
+            dailyRepayCount
+            dailyLiquidateCount
+            dailyTransferCount
+            dailyFlashloanCount
+            openPositionCount
+            dailyActivePositions
+            totalPoolCount
+        }
+    }
+    "#;
+
+    let variables = json!({
+        "from": from.to_string(),
+        "to": to.to_string(),
+    });
+
+    let mut snapshots: Vec<UsageMetricsDailySnapshot> = client
+        .query_paginated(query, "usageMetricsDailySnapshots", variables, None)
+        .await?;
+    snapshots.sort_by_key(|snapshot| snapshot.days);
+    Ok(snapshots)
+}
+
+/// Fetch the hourly usage snapshots with a timestamp in `[from, to)`, oldest first
+pub async fn fetch_usage_hourly_snapshots(
+    client: &GraphClient,
+    from: i64,
+    to: i64,
+) -> Result<Vec<UsageMetricsHourlySnapshot>> {
+    let query = r#"
+    query MorphoUsageMetricsHourlySnapshots($first: Int!, $lastId: Bytes!, $from: BigInt!, $to: BigInt!) {
+        usageMetricsHourlySnapshots(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, timestamp_gte: $from, timestamp_lt: $to }
+        ) {
+            id
+            hours
+            blockNumber
+            timestamp
+            hourlyActiveUsers
+            cumulativeUniqueUsers
+            hourlyTransactionCount
+            hourlyDepositCount
+            hourlyWithdrawCount
+            hourlyBorrowCount
+            hourlyRepayCount
+            hourlyLiquidateCount
+        }
+    }
+    "#;
+
+    let variables = json!({
+        "from": from.to_string(),
+        "to": to.to_string(),
+    });
+
+    let mut snapshots: Vec<UsageMetricsHourlySnapshot> = client
+        .query_paginated(query, "usageMetricsHourlySnapshots", variables, None)
+        .await?;
+    snapshots.sort_by_key(|snapshot| snapshot.hours);
+    Ok(snapshots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overview_utilization_and_lltvs() {
+        let overview: ProtocolOverview = serde_json::from_value(json!({
+            "id": "0xmorpho",
+            "name": "Morpho Blue",
+            "slug": "morpho-blue",
+            "network": "BASE",
+            "totalValueLockedUSD": "1000",
+            "totalDepositBalanceUSD": "800",
+            "totalBorrowBalanceUSD": "200",
+            "cumulativeSupplySideRevenueUSD": "0",
+            "cumulativeProtocolSideRevenueUSD": "0",
+            "cumulativeTotalRevenueUSD": "0",
+            "cumulativeUniqueUsers": 10,
+            "cumulativeUniqueDepositors": 8,
+            "cumulativeUniqueBorrowers": 2,
+            "totalPoolCount": 3,
+            "openPositionCount": 5,
+            "transactionCount": 20,
+            "owner": "0xowner",
+            "feeRecipient": "0xrecipient",
+            "irmEnabled": ["0xirm"],
+            "lltvEnabled": ["945000000000000000", "0", "860000000000000000"],
+        }))
+        .unwrap();
+
+        assert_eq!(overview.utilization(), 0.25);
+        assert_eq!(overview.lltvs(), vec![0.0, 0.86, 0.945]);
+        assert!(overview.fees.is_none());
+    }
+}
@@ -0,0 +1,574 @@
+//! Morpho Public Allocator: flow caps, reallocation history and reallocatable liquidity.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::EntityRef;
+use crate::client::GraphClient;
+use crate::numeric::to_f64;
+
+/// Vault fields selected alongside public allocator data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllocatorVault {
+    pub id: String,
+    pub name: String,
+}
+
+/// Public allocator configuration of a vault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicAllocatorRef {
+    pub id: String,
+    /// Fee charged per public reallocation, in native units of the chain's gas token
+    pub fee: String,
+    #[serde(rename = "metaMorpho")]
+    pub meta_morpho: AllocatorVault,
+}
+
+/// Morpho Blue market state needed to size a reallocation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidityMarket {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "totalSupply")]
+    pub total_supply: String,
+    #[serde(rename = "totalBorrow")]
+    pub total_borrow: String,
+}
+
+/// A vault market seen through the public allocator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowCapMarket {
+    /// Id of the `MetaMorphoMarket` entity
+    pub id: String,
+    pub cap: String,
+    pub enabled: bool,
+    pub market: LiquidityMarket,
+}
+
+/// Current public allocator flow caps of a vault on one market, in native units
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowCap {
+    pub id: String,
+    #[serde(rename = "metaMorphoPublicAllocator")]
+    pub public_allocator: PublicAllocatorRef,
+    pub market: FlowCapMarket,
+    #[serde(rename = "flowCapIn")]
+    pub flow_cap_in: String,
+    #[serde(rename = "flowCapOut")]
+    pub flow_cap_out: String,
+}
+
+/// Selects which flow caps [`fetch_flow_caps`] returns
+#[derive(Debug, Clone, Default)]
+pub struct FlowCapFilter {
+    /// Only return flow caps of these vaults
+    pub vaults: Option<Vec<String>>,
+    /// Only return flow caps on this Morpho Blue market
+    pub market: Option<String>,
+}
+
+impl FlowCapFilter {
+    fn to_where(&self) -> Value {
+        let mut filter = Map::new();
+        if let Some(vaults) = &self.vaults {
+            let vaults: Vec<String> = vaults.iter().map(|v| v.to_lowercase()).collect();
+            filter.insert("metaMorphoPublicAllocator_in".to_string(), json!(vaults));
+        }
+        if let Some(market) = &self.market {
+            filter.insert(
+                "market_".to_string(),
+                json!({ "market": market.to_lowercase() }),
+            );
+        }
+        Value::Object(filter)
+    }
+}
+
+/// Direction of a public reallocation, seen from the market
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReallocationDirection {
+    /// Assets were supplied to the market
+    In,
+    /// Assets were withdrawn from the market
+    Out,
+}
+
+/// A single leg of a public reallocation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reallocation {
+    pub id: String,
+    pub hash: String,
+    pub block_number: String,
+    pub timestamp: String,
+    pub author: String,
+    pub vault: Option<String>,
+    pub market: Option<String>,
+    pub direction: ReallocationDirection,
+    /// Assets moved, in native units
+    pub assets: String,
+}
+
+/// Selects which reallocations [`fetch_reallocations`] returns
+#[derive(Debug, Clone, Default)]
+pub struct ReallocationFilter {
+    pub vault: Option<String>,
+    pub author: Option<String>,
+    /// Inclusive lower bound on the event timestamp
+    pub from: Option<i64>,
+    /// Exclusive upper bound on the event timestamp
+    pub to: Option<i64>,
+    /// Maximum number of reallocations, newest first
+    pub limit: Option<usize>,
+}
+
+impl ReallocationFilter {
+    fn to_where(&self) -> Value {
+        let mut filter = Map::new();
+        if let Some(vault) = &self.vault {
+            filter.insert(
+                "metaMorphoPublicAllocator".to_string(),
+                json!(vault.to_lowercase()),
+            );
+        }
+        if let Some(author) = &self.author {
+            filter.insert("author".to_string(), json!(author.to_lowercase()));
+        }
+        if let Some(from) = self.from {
+            filter.insert("timestamp_gte".to_string(), json!(from.to_string()));
+        }
+        if let Some(to) = self.to {
+            filter.insert("timestamp_lt".to_string(), json!(to.to_string()));
+        }
+        Value::Object(filter)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ReallocationEntity {
+    id: String,
+    hash: String,
+    #[serde(rename = "blockNumber")]
+    block_number: String,
+    timestamp: String,
+    author: EntityRef,
+    #[serde(rename = "metaMorphoPublicAllocator")]
+    public_allocator: Option<EntityRef>,
+    #[serde(rename = "marketPublicAllocator")]
+    market_public_allocator: Option<AllocatorMarketRef>,
+    #[serde(rename = "suppliedAssets")]
+    supplied_assets: Option<String>,
+    #[serde(rename = "withdrawnAssets")]
+    withdrawn_assets: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct AllocatorMarketRef {
+    market: VaultMarketRef,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct VaultMarketRef {
+    market: EntityRef,
+}
+
+impl From<ReallocationEntity> for Reallocation {
+    fn from(entity: ReallocationEntity) -> Self {
+        let (direction, assets) = match (entity.supplied_assets, entity.withdrawn_assets) {
+            (Some(supplied), _) => (ReallocationDirection::In, supplied),
+            (None, withdrawn) => (ReallocationDirection::Out, withdrawn.unwrap_or_default()),
+        };
+
+        Reallocation {
+            id: entity.id,
+            hash: entity.hash,
+            block_number: entity.block_number,
+            timestamp: entity.timestamp,
+            author: entity.author.id,
+            vault: entity.public_allocator.map(|allocator| allocator.id),
+            market: entity
+                .market_public_allocator
+                .map(|allocator| allocator.market.market.id),
+            direction,
+            assets,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct SupplyPosition {
+    account: EntityRef,
+    market: EntityRef,
+    balance: String,
+}
+
+/// Liquidity a single vault can move into a market through the public allocator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultReallocatable {
+    pub vault: String,
+    pub vault_name: String,
+    /// Remaining flow cap into the target market
+    pub flow_cap_in: f64,
+    /// Room left under the vault's supply cap on the target market
+    pub remaining_cap: f64,
+    /// Assets the vault can pull out of its other markets
+    pub withdrawable_from_sources: f64,
+    /// Smallest of the three bounds above
+    pub reallocatable: f64,
+}
+
+/// Liquidity that can be pulled into a market through the public allocator, in native units
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReallocatableLiquidity {
+    pub market: String,
+    pub total: f64,
+    /// Per-vault breakdown, largest first
+    pub vaults: Vec<VaultReallocatable>,
+}
+
+/// Fetch the current public allocator flow caps matching `filter`
+pub async fn fetch_flow_caps(client: &GraphClient, filter: &FlowCapFilter) -> Result<Vec<FlowCap>> {
+    let query = r#"
+    query MorphoFlowCaps($first: Int!, $lastId: Bytes!, $where: MetaMorphoPublicAllocatorMarket_filter!) {
+        metaMorphoPublicAllocatorMarkets(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { and: [{ id_gt: $lastId }, $where] }
+        ) {
+            id
+            metaMorphoPublicAllocator {
+                id
+                fee
+                metaMorpho {
+                    id
+                    name
+                }
+            }
+            market {
+                id
+                cap
+                enabled
+                market {
+                    id
+                    name
+                    totalSupply
+                    totalBorrow
+                }
+            }
+            flowCapIn
+            flowCapOut
+        }
+    }
+    "#;
+
+    let variables = json!({
+        "where": filter.to_where(),
+    });
+
+    client
+        .query_paginated(query, "metaMorphoPublicAllocatorMarkets", variables, None)
+        .await
+}
+
+/// Fetch public reallocations into and out of markets, newest first
+pub async fn fetch_reallocations(
+    client: &GraphClient,
+    filter: &ReallocationFilter,
+) -> Result<Vec<Reallocation>> {
+    let supplies_query = r#"
+    query MorphoReallocationsTo($first: Int!, $lastId: Bytes!, $where: PublicAllocatorReallocationToEvent_filter!, $orderBy: PublicAllocatorReallocationToEvent_orderBy!, $orderDirection: OrderDirection!) {
+        publicAllocatorReallocationToEvents(
+            first: $first,
+            orderBy: $orderBy,
+            orderDirection: $orderDirection,
+            where: { and: [{ id_gt: $lastId }, $where] }
+        ) {
+            id
+            hash
+            blockNumber
+            timestamp
+            author {
+                id
+            }
+            metaMorphoPublicAllocator {
+                id
+            }
+            marketPublicAllocator {
+                market {
+                    market {
+                        id
+                    }
+                }
+            }
+            suppliedAssets
+        }
+    }
+    "#;
+
+    let withdrawals_query = r#"
+    query MorphoReallocationWithdrawals($first: Int!, $lastId: Bytes!, $where: PublicAllocatorWithdrawalEvent_filter!, $orderBy: PublicAllocatorWithdrawalEvent_orderBy!, $orderDirection: OrderDirection!) {
+        publicAllocatorWithdrawalEvents(
+            first: $first,
+            orderBy: $orderBy,
+            orderDirection: $orderDirection,
+            where: { and: [{ id_gt: $lastId }, $where] }
+        ) {
+            id
+            hash
+            blockNumber
+            timestamp
+            author {
+                id
+            }
+            metaMorphoPublicAllocator {
+                id
+            }
+            marketPublicAllocator {
+                market {
+                    market {
+                        id
+                    }
+                }
+            }
+            withdrawnAssets
+        }
+    }
+    "#;
+
+    let variables = json!({
+        "where": filter.to_where(),
+    });
+
+    let supplies: Vec<ReallocationEntity> = client
+        .query_newest(
+            supplies_query,
+            "publicAllocatorReallocationToEvents",
+            "timestamp",
+            variables.clone(),
+            filter.limit,
+        )
+        .await?;
+    let withdrawals: Vec<ReallocationEntity> = client
+        .query_newest(
+            withdrawals_query,
+            "publicAllocatorWithdrawalEvents",
+            "timestamp",
+            variables,
+            filter.limit,
+        )
+        .await?;
+
+    let mut reallocations: Vec<Reallocation> = supplies
+        .into_iter()
+        .chain(withdrawals)
+        .map(Reallocation::from)
+        .collect();
+    reallocations.sort_by_key(|r| std::cmp::Reverse(r.timestamp.parse::<i64>().unwrap_or(0)));
+    if let Some(limit) = filter.limit {
+        reallocations.truncate(limit);
+    }
+    Ok(reallocations)
+}
+
+/// Compute how much liquidity can currently be reallocated into `market` across all vaults
+pub async fn fetch_reallocatable_liquidity(
+    client: &GraphClient,
+    market: &str,
+) -> Result<ReallocatableLiquidity> {
+    let market = market.to_lowercase();
+    let targets = fetch_flow_caps(
+        client,
+        &FlowCapFilter {
+            vaults: None,
+            market: Some(market.clone()),
+        },
+    )
+    .await?;
+
+    let vaults: Vec<String> = targets
+        .iter()
+        .map(|cap| cap.public_allocator.meta_morpho.id.clone())
+        .collect();
+    if vaults.is_empty() {
+        return Ok(ReallocatableLiquidity {
+            market,
+            total: 0.0,
+            vaults: Vec::new(),
+        });
+    }
+
+    let flow_caps = fetch_flow_caps(
+        client,
+        &FlowCapFilter {
+            vaults: Some(vaults.clone()),
+            market: None,
+        },
+    )
+    .await?;
+
+    let positions_query = r#"
+    query MorphoVaultSupplyPositions($first: Int!, $lastId: ID!, $accounts: [String!]!) {
+        positions(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, account_in: $accounts, side: SUPPLIER, balance_gt: "0" }
+        ) {
+            id
+            account {
+                id
+            }
+            market {
+                id
+            }
+            balance
+        }
+    }
+    "#;
+
+    let positions: Vec<SupplyPosition> = client
+        .query_paginated(
+            positions_query,
+            "positions",
+            json!({ "accounts": vaults }),
+            None,
+        )
+        .await?;
+
+    Ok(reallocatable_liquidity(&market, &flow_caps, &positions))
+}
+
+fn reallocatable_liquidity(
+    market: &str,
+    flow_caps: &[FlowCap],
+    positions: &[SupplyPosition],
+) -> ReallocatableLiquidity {
+    let supplied: HashMap<(&str, &str), f64> = positions
+        .iter()
+        .map(|p| {
+            (
+                (p.account.id.as_str(), p.market.id.as_str()),
+                to_f64(&p.balance),
+            )
+        })
+        .collect();
+    let supplied_by = |vault: &str, market: &str| -> f64 {
+        supplied.get(&(vault, market)).copied().unwrap_or(0.0)
+    };
+
+    let mut vaults: Vec<VaultReallocatable> = flow_caps
+        .iter()
+        .filter(|cap| cap.market.market.id == market && cap.market.enabled)
+        .map(|target| {
+            let vault = &target.public_allocator.meta_morpho;
+            let flow_cap_in = to_f64(&target.flow_cap_in);
+            let remaining_cap =
+                (to_f64(&target.market.cap) - supplied_by(&vault.id, market)).max(0.0);
+
+            let withdrawable_from_sources = flow_caps
+                .iter()
+                .filter(|source| {
+                    source.public_allocator.meta_morpho.id == vault.id
+                        && source.market.market.id != market
+                })
+                .map(|source| {
+                    let source_liquidity = to_f64(&source.market.market.total_supply)
+                        - to_f64(&source.market.market.total_borrow);
+                    to_f64(&source.flow_cap_out)
+                        .min(supplied_by(&vault.id, &source.market.market.id))
+                        .min(source_liquidity)
+                        .max(0.0)
+                })
+                .sum::<f64>();
+
+            VaultReallocatable {
+                vault: vault.id.clone(),
+                vault_name: vault.name.clone(),
+                flow_cap_in,
+                remaining_cap,
+                withdrawable_from_sources,
+                reallocatable: flow_cap_in
+                    .min(remaining_cap)
+                    .min(withdrawable_from_sources),
+            }
+        })
+        .collect();
+    vaults.sort_by(|a, b| b.reallocatable.total_cmp(&a.reallocatable));
+
+    ReallocatableLiquidity {
+        market: market.to_string(),
+        total: vaults.iter().map(|v| v.reallocatable).sum(),
+        vaults,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow_cap(vault: &str, market: &str, cap: &str, cap_in: &str, cap_out: &str) -> FlowCap {
+        FlowCap {
+            id: format!("{}-{}", vault, market),
+            public_allocator: PublicAllocatorRef {
+                id: vault.to_string(),
+                fee: "0".to_string(),
+                meta_morpho: AllocatorVault {
+                    id: vault.to_string(),
+                    name: vault.to_string(),
+                },
+            },
+            market: FlowCapMarket {
+                id: format!("{}-{}", vault, market),
+                cap: cap.to_string(),
+                enabled: true,
+                market: LiquidityMarket {
+                    id: market.to_string(),
+                    name: market.to_string(),
+                    total_supply: "1000".to_string(),
+                    total_borrow: "700".to_string(),
+                },
+            },
+            flow_cap_in: cap_in.to_string(),
+            flow_cap_out: cap_out.to_string(),
+        }
+    }
+
+    fn position(vault: &str, market: &str, balance: &str) -> SupplyPosition {
+        SupplyPosition {
+            account: EntityRef {
+                id: vault.to_string(),
+            },
+            market: EntityRef {
+                id: market.to_string(),
+            },
+            balance: balance.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_reallocatable_is_bounded_by_caps_and_sources() {
+        let flow_caps = vec![
+            flow_cap("0xvault", "0xtarget", "500", "400", "0"),
+            flow_cap("0xvault", "0xa", "1000", "0", "250"),
+            flow_cap("0xvault", "0xb", "1000", "0", "1000"),
+        ];
+        let positions = vec![
+            position("0xvault", "0xtarget", "200"),
+            position("0xvault", "0xa", "500"),
+            position("0xvault", "0xb", "50"),
+        ];
+
+        let liquidity = reallocatable_liquidity("0xtarget", &flow_caps, &positions);
+
+        // Source a is limited by its flow cap out (250), source b by the vault's supply (50)
+        let vault = &liquidity.vaults[0];
+        assert_eq!(vault.withdrawable_from_sources, 300.0);
+        // Target cap 500 minus 200 already supplied
+        assert_eq!(vault.remaining_cap, 300.0);
+        assert_eq!(vault.reallocatable, 300.0);
+        assert_eq!(liquidity.total, 300.0);
+    }
+}
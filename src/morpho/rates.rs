@@ -0,0 +1,166 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::snapshots::side_rate;
+use super::{Decimal, GraphQLResponse, InterestRate, MarketRef, TokenRef};
+use crate::client::{GraphClient, MAX_PAGE_SIZE};
+use crate::numeric::{ratio, to_f64};
+
+/// Selects which markets a market-level query returns
+#[derive(Debug, Clone, Default)]
+pub struct MarketFilter {
+    /// Only return these market ids
+    pub market_ids: Option<Vec<String>>,
+    /// Skip markets that are frozen
+    pub active_only: bool,
+    /// Maximum number of markets, ordered by total value locked. Queries ordered by value
+    /// cannot use the id cursor, so at most [`MAX_PAGE_SIZE`] markets are returned.
+    pub limit: Option<usize>,
+}
+
+impl MarketFilter {
+    /// Build the `Market_filter` object used in the `where` argument
+    pub(crate) fn to_where(&self) -> Value {
+        let mut filter = Map::new();
+        if let Some(ids) = &self.market_ids {
+            let ids: Vec<String> = ids.iter().map(|id| id.to_lowercase()).collect();
+            filter.insert("id_in".to_string(), json!(ids));
+        }
+        if self.active_only {
+            filter.insert("isActive".to_string(), json!(true));
+        }
+        Value::Object(filter)
+    }
+
+    /// Page size for the query, `limit` capped at [`MAX_PAGE_SIZE`]
+    pub(crate) fn first(&self) -> usize {
+        self.limit.unwrap_or(MAX_PAGE_SIZE).min(MAX_PAGE_SIZE)
+    }
+}
+
+/// Supply and borrow rates of a market joined with its utilization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketRates {
+    pub market: MarketRef,
+    /// Lender rate in percent, as reported by the subgraph, if the market has one
+    pub supply_apy: Option<f64>,
+    /// Borrower rate in percent, as reported by the subgraph, if the market has one
+    pub borrow_apy: Option<f64>,
+    /// Share of deposits that is currently borrowed, between 0 and 1
+    pub utilization: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RatedMarket {
+    id: String,
+    name: String,
+    #[serde(rename = "inputToken")]
+    input_token: TokenRef,
+    #[serde(default)]
+    rates: Option<Vec<InterestRate>>,
+    #[serde(rename = "totalDepositBalanceUSD")]
+    total_deposit_balance_usd: Decimal,
+    #[serde(rename = "totalBorrowBalanceUSD")]
+    total_borrow_balance_usd: Decimal,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RatedMarketsResponse {
+    markets: Vec<RatedMarket>,
+}
+
+impl From<RatedMarket> for MarketRates {
+    fn from(market: RatedMarket) -> Self {
+        MarketRates {
+            supply_apy: side_rate(market.rates.as_deref(), "LENDER"),
+            borrow_apy: side_rate(market.rates.as_deref(), "BORROWER"),
+            utilization: ratio(
+                to_f64(&market.total_borrow_balance_usd),
+                to_f64(&market.total_deposit_balance_usd),
+            ),
+            market: MarketRef {
+                id: market.id,
+                name: market.name,
+                input_token: market.input_token,
+            },
+        }
+    }
+}
+
+/// Fetch supply/borrow rates and utilization for each market, ordered by total value locked
+pub async fn fetch_market_rates(
+    client: &GraphClient,
+    filter: &MarketFilter,
+) -> Result<Vec<MarketRates>> {
+    let query = r#"
+    query MorphoMarketRates($first: Int, $where: Market_filter) {
+        markets(first: $first, orderBy: totalValueLockedUSD, orderDirection: desc, where: $where) {
+            id
+            name
+            inputToken {
+                symbol
+            }
+            rates {
+                id
+                rate
+                side
+                type
+            }
+            totalDepositBalanceUSD
+            totalBorrowBalanceUSD
+        }
+    }
+    "#;
+
+    let variables = json!({
+        "first": filter.first(),
+        "where": filter.to_where(),
+    });
+
+    let response: GraphQLResponse<RatedMarketsResponse> =
+        client.query_raw(query, variables).await?;
+    Ok(response
+        .data
+        .markets
+        .into_iter()
+        .map(MarketRates::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(side: &str, value: &str) -> InterestRate {
+        InterestRate {
+            id: format!("{}-rate", side),
+            rate: value.to_string(),
+            side: side.to_string(),
+            rate_type: "VARIABLE".to_string(),
+        }
+    }
+
+    fn market(rates: Option<Vec<InterestRate>>) -> RatedMarket {
+        RatedMarket {
+            id: "0xmarket".to_string(),
+            name: "WETH/USDC".to_string(),
+            input_token: TokenRef {
+                symbol: "WETH".to_string(),
+            },
+            rates,
+            total_deposit_balance_usd: "200".to_string(),
+            total_borrow_balance_usd: "50".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_missing_side_rate_is_none() {
+        let rates = MarketRates::from(market(Some(vec![rate("BORROWER", "4.5")])));
+
+        assert_eq!(rates.supply_apy, None);
+        assert_eq!(rates.borrow_apy, Some(4.5));
+        assert_eq!(rates.utilization, 0.25);
+        assert_eq!(MarketRates::from(market(None)).borrow_apy, None);
+    }
+}
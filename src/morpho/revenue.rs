@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{Decimal, EntityRef, GraphQLResponse, MarketFilter};
+use crate::client::GraphClient;
+use crate::numeric::{ratio, to_f64};
+
+/// Scale of the onchain market fee, 1e18 is 100%
+const WAD: f64 = 1e18;
+
+/// A fee charged by the protocol or a market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fee {
+    pub id: String,
+    /// Fee in percent, e.g. 5.21 for 5.21%
+    pub rate: Option<Decimal>,
+    /// `PROTOCOL_FEE`, `LIQUIDATION_FEE`, `FLASHLOAN_LP_FEE`, ...
+    #[serde(rename = "type")]
+    pub fee_type: String,
+}
+
+/// Cumulative revenue per fee source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevenueDetail {
+    pub id: String,
+    pub sources: Vec<Fee>,
+    /// Revenue in USD, in the same order as `sources`
+    #[serde(rename = "amountsUSD")]
+    pub amounts_usd: Vec<Decimal>,
+}
+
+impl RevenueDetail {
+    /// Revenue in USD keyed by fee type
+    pub fn by_fee_type(&self) -> HashMap<String, f64> {
+        let mut amounts = HashMap::new();
+        for (source, amount) in self.sources.iter().zip(&self.amounts_usd) {
+            *amounts.entry(source.fee_type.clone()).or_insert(0.0) += to_f64(amount);
+        }
+        amounts
+    }
+}
+
+/// Revenue earned from one fee source over a window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceRevenue {
+    pub fee_type: String,
+    pub revenue_usd: f64,
+}
+
+/// Revenue of a market over a window, split by side and by fee source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketRevenue {
+    pub market_id: String,
+    pub market_name: String,
+    /// Current market fee as a fraction of interest, between 0 and 1
+    pub fee: f64,
+    /// Interest paid to suppliers
+    pub supply_side_revenue_usd: f64,
+    /// Interest kept by the protocol through the market fee
+    pub protocol_side_revenue_usd: f64,
+    pub total_revenue_usd: f64,
+    /// Share of total revenue kept by the protocol, between 0 and 1
+    pub protocol_share: f64,
+    /// Largest source first
+    pub by_source: Vec<SourceRevenue>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RevenueMarket {
+    id: String,
+    name: String,
+    fee: String,
+    /// Last daily snapshot before the window
+    #[serde(default)]
+    baseline: Vec<RevenueSnapshot>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RevenueMarketsResponse {
+    markets: Vec<RevenueMarket>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RevenueSnapshot {
+    market: EntityRef,
+    days: i32,
+    #[serde(rename = "cumulativeSupplySideRevenueUSD")]
+    cumulative_supply_side_revenue_usd: Decimal,
+    #[serde(rename = "cumulativeProtocolSideRevenueUSD")]
+    cumulative_protocol_side_revenue_usd: Decimal,
+    #[serde(rename = "revenueDetail")]
+    revenue_detail: Option<RevenueDetail>,
+}
+
+/// Fetch the revenue of each market matching `filter` over `[from, to)`, ranked by protocol
+/// revenue. Revenue is the difference between the cumulative totals of the last daily snapshot
+/// in the window and the last one before it.
+pub async fn fetch_market_revenue(
+    client: &GraphClient,
+    filter: &MarketFilter,
+    from: i64,
+    to: i64,
+) -> Result<Vec<MarketRevenue>> {
+    let query = r#"
+    query MorphoRevenueMarkets($first: Int, $where: Market_filter, $from: BigInt!) {
+        markets(first: $first, orderBy: totalValueLockedUSD, orderDirection: desc, where: $where) {
+            id
+            name
+            fee
+            baseline: dailySnapshots(first: 1, orderBy: timestamp, orderDirection: desc, where: { timestamp_lt: $from }) {
+                market {
+                    id
+                }
+                days
+                cumulativeSupplySideRevenueUSD
+                cumulativeProtocolSideRevenueUSD
+                revenueDetail {
+                    id
+                    sources {
+                        id
+                        rate
+                        type
+                    }
+                    amountsUSD
+                }
+            }
+        }
+    }
+    "#;
+
+    let variables = json!({
+        "first": filter.first(),
+        "where": filter.to_where(),
+        "from": from.to_string(),
+    });
+
+    let response: GraphQLResponse<RevenueMarketsResponse> =
+        client.query_raw(query, variables).await?;
+    let markets = response.data.markets;
+    if markets.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let snapshots_query = r#"
+    query MorphoRevenueSnapshots($first: Int!, $lastId: Bytes!, $markets: [String!]!, $from: BigInt!, $to: BigInt!) {
+        marketDailySnapshots(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, market_in: $markets, timestamp_gte: $from, timestamp_lt: $to }
+        ) {
+            market {
+                id
+            }
+            id
+            days
+            cumulativeSupplySideRevenueUSD
+            cumulativeProtocolSideRevenueUSD
+            revenueDetail {
+                id
+                sources {
+                    id
+                    rate
+                    type
+                }
+                amountsUSD
+            }
+        }
+    }
+    "#;
+
+    let ids: Vec<&str> = markets.iter().map(|m| m.id.as_str()).collect();
+    let variables = json!({
+        "markets": ids,
+        "from": from.to_string(),
+        "to": to.to_string(),
+    });
+
+    let snapshots: Vec<RevenueSnapshot> = client
+        .query_paginated(snapshots_query, "marketDailySnapshots", variables, None)
+        .await?;
+    let mut by_market: HashMap<String, Vec<RevenueSnapshot>> = HashMap::new();
+    for snapshot in snapshots {
+        by_market
+            .entry(snapshot.market.id.clone())
+            .or_default()
+            .push(snapshot);
+    }
+
+    let mut revenues: Vec<MarketRevenue> = markets
+        .into_iter()
+        .map(|market| {
+            let snapshots = by_market.remove(&market.id).unwrap_or_default();
+            market_revenue(market, snapshots)
+        })
+        .collect();
+    rank_by_protocol_revenue(&mut revenues);
+    Ok(revenues)
+}
+
+/// Sort markets by protocol revenue, largest first
+pub fn rank_by_protocol_revenue(revenues: &mut [MarketRevenue]) {
+    revenues.sort_by(|a, b| {
+        b.protocol_side_revenue_usd
+            .total_cmp(&a.protocol_side_revenue_usd)
+    });
+}
+
+/// Revenue of a market between its baseline snapshot and the last snapshot of the window
+fn market_revenue(market: RevenueMarket, mut snapshots: Vec<RevenueSnapshot>) -> MarketRevenue {
+    snapshots.sort_by_key(|snapshot| snapshot.days);
+    let baseline = market.baseline.first();
+    let end = snapshots.last();
+
+    let cumulative = |snapshot: Option<&RevenueSnapshot>| {
+        snapshot
+            .map(|s| {
+                (
+                    to_f64(&s.cumulative_supply_side_revenue_usd),
+                    to_f64(&s.cumulative_protocol_side_revenue_usd),
+                    s.revenue_detail
+                        .as_ref()
+                        .map(RevenueDetail::by_fee_type)
+                        .unwrap_or_default(),
+                )
+            })
+            .unwrap_or_default()
+    };
+    let (start_supply, start_protocol, start_sources) = cumulative(baseline);
+    let (end_supply, end_protocol, end_sources) = match end {
+        Some(_) => cumulative(end),
+        // No snapshot in the window, so nothing was earned
+        None => (start_supply, start_protocol, start_sources.clone()),
+    };
+
+    let mut by_source: Vec<SourceRevenue> = end_sources
+        .into_iter()
+        .map(|(fee_type, amount)| SourceRevenue {
+            revenue_usd: amount - start_sources.get(&fee_type).copied().unwrap_or(0.0),
+            fee_type,
+        })
+        .filter(|source| source.revenue_usd != 0.0)
+        .collect();
+    by_source.sort_by(|a, b| b.revenue_usd.total_cmp(&a.revenue_usd));
+
+    let supply_side_revenue_usd = end_supply - start_supply;
+    let protocol_side_revenue_usd = end_protocol - start_protocol;
+    let total_revenue_usd = supply_side_revenue_usd + protocol_side_revenue_usd;
+
+    MarketRevenue {
+        market_id: market.id,
+        market_name: market.name,
+        fee: to_f64(&market.fee) / WAD,
+        supply_side_revenue_usd,
+        protocol_side_revenue_usd,
+        total_revenue_usd,
+        protocol_share: ratio(protocol_side_revenue_usd, total_revenue_usd),
+        by_source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(days: i32, supply: &str, protocol: &str, liquidation_fee: &str) -> RevenueSnapshot {
+        RevenueSnapshot {
+            market: EntityRef {
+                id: "0xmarket".to_string(),
+            },
+            days,
+            cumulative_supply_side_revenue_usd: supply.to_string(),
+            cumulative_protocol_side_revenue_usd: protocol.to_string(),
+            revenue_detail: Some(RevenueDetail {
+                id: format!("0xmarket-{}", days),
+                sources: vec![
+                    Fee {
+                        id: "PROTOCOL_FEE".to_string(),
+                        rate: None,
+                        fee_type: "PROTOCOL_FEE".to_string(),
+                    },
+                    Fee {
+                        id: "LIQUIDATION_FEE".to_string(),
+                        rate: None,
+                        fee_type: "LIQUIDATION_FEE".to_string(),
+                    },
+                ],
+                amounts_usd: vec![protocol.to_string(), liquidation_fee.to_string()],
+            }),
+        }
+    }
+
+    #[test]
+    fn test_market_revenue_uses_snapshot_deltas() {
+        let market = RevenueMarket {
+            id: "0xmarket".to_string(),
+            name: "WETH/USDC".to_string(),
+            fee: "100000000000000000".to_string(),
+            baseline: vec![snapshot(10, "500", "50", "10")],
+        };
+        let snapshots = vec![
+            snapshot(12, "900", "100", "50"),
+            snapshot(11, "700", "80", "30"),
+        ];
+
+        let revenue = market_revenue(market, snapshots);
+
+        assert!((revenue.fee - 0.1).abs() < 1e-12);
+        assert_eq!(revenue.supply_side_revenue_usd, 400.0);
+        assert_eq!(revenue.protocol_side_revenue_usd, 50.0);
+        assert!((revenue.protocol_share - 50.0 / 450.0).abs() < 1e-12);
+        assert_eq!(revenue.by_source.len(), 2);
+        assert_eq!(revenue.by_source[0].fee_type, "PROTOCOL_FEE");
+        assert_eq!(revenue.by_source[0].revenue_usd, 50.0);
+        assert_eq!(revenue.by_source[1].revenue_usd, 40.0);
+    }
+}
@@ -0,0 +1,272 @@
+//! Health factor, LTV and liquidation price of Morpho Blue borrowing positions.
+//!
+//! For a borrower with collateral value `C` and debt value `D` in a market with
+//! liquidation threshold `LT`:
+//!
+//! - `ltv = D / C`
+//! - `health_factor = C * LT / D`, the position is liquidatable below 1
+//! - `distance_to_liquidation = 1 - 1 / health_factor`, the relative collateral price drop
+//!   that makes the position liquidatable
+//! - `liquidation_price = D / (collateral_amount * LT)`, in USD per collateral token
+//!
+//! Health factor and distance to liquidation are unknown when the loan token or the
+//! collateral has no USD price, rather than zero, which would read as liquidatable.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::portfolio::POSITION_FIELDS;
+use super::Position;
+use crate::client::GraphClient;
+use crate::numeric::{ratio, to_f64};
+
+/// Risk metrics of one account's borrow in a market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionRisk {
+    pub account: String,
+    pub market_id: String,
+    pub market_name: String,
+    /// Collateral in whole tokens
+    pub collateral_amount: f64,
+    pub collateral_usd: f64,
+    /// Debt in whole tokens
+    pub debt_amount: f64,
+    pub debt_usd: f64,
+    /// Current loan-to-value, between 0 and 1
+    pub ltv: f64,
+    /// Maximum loan-to-value at origination, between 0 and 1
+    pub max_ltv: f64,
+    /// Loan-to-value at which the position becomes liquidatable, between 0 and 1
+    pub liquidation_threshold: f64,
+    /// Zero when the position has no collateral, `None` when a price is missing
+    pub health_factor: Option<f64>,
+    /// Relative collateral price drop that makes the position liquidatable, `None` when a
+    /// price is missing
+    pub distance_to_liquidation: Option<f64>,
+    /// Collateral price in USD at which the position becomes liquidatable
+    pub liquidation_price: Option<f64>,
+}
+
+impl PositionRisk {
+    /// Whether the position can currently be liquidated, false when a price is missing
+    pub fn is_liquidatable(&self) -> bool {
+        self.health_factor
+            .is_some_and(|health_factor| health_factor < 1.0)
+    }
+}
+
+/// Compute risk metrics for a borrow position and the matching collateral position, if any
+pub fn compute_position_risk(borrow: &Position, collateral: Option<&Position>) -> PositionRisk {
+    let liquidation_threshold = to_f64(&borrow.market.liquidation_threshold) / 100.0;
+    let max_ltv = to_f64(&borrow.market.maximum_ltv) / 100.0;
+
+    let debt_amount = borrow.amount();
+    let debt_usd = borrow.balance_usd();
+    let collateral_amount = collateral.map(Position::amount).unwrap_or(0.0);
+    let collateral_usd = collateral.map(Position::balance_usd).unwrap_or(0.0);
+
+    let priced = borrow.price_usd() > 0.0 && collateral.is_none_or(|c| c.price_usd() > 0.0);
+
+    let health_factor = priced.then(|| ratio(collateral_usd * liquidation_threshold, debt_usd));
+    let distance_to_liquidation = health_factor.map(|health_factor| {
+        if health_factor > 0.0 {
+            1.0 - 1.0 / health_factor
+        } else {
+            0.0
+        }
+    });
+    let liquidation_price = if priced && collateral_amount > 0.0 && liquidation_threshold > 0.0 {
+        Some(debt_usd / (collateral_amount * liquidation_threshold))
+    } else {
+        None
+    };
+
+    PositionRisk {
+        account: borrow.account.id.clone(),
+        market_id: borrow.market.id.clone(),
+        market_name: borrow.market.name.clone(),
+        collateral_amount,
+        collateral_usd,
+        debt_amount,
+        debt_usd,
+        ltv: ratio(debt_usd, collateral_usd),
+        max_ltv,
+        liquidation_threshold,
+        health_factor,
+        distance_to_liquidation,
+        liquidation_price,
+    }
+}
+
+/// Sort positions so that the ones closest to liquidation come first, unpriced ones last
+pub fn sort_by_liquidation_proximity(positions: &mut [PositionRisk]) {
+    positions.sort_by(|a, b| match (a.health_factor, b.health_factor) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+}
+
+/// Pair each borrow position with the same account's collateral and compute its risk
+fn risks_from_positions(positions: Vec<Position>) -> Vec<PositionRisk> {
+    let (borrows, collaterals): (Vec<Position>, Vec<Position>) = positions
+        .into_iter()
+        .partition(|position| position.side == "BORROWER");
+    let collateral_by_account: HashMap<(&str, &str), &Position> = collaterals
+        .iter()
+        .map(|c| ((c.account.id.as_str(), c.market.id.as_str()), c))
+        .collect();
+
+    let mut risks: Vec<PositionRisk> = borrows
+        .iter()
+        .map(|borrow| {
+            let collateral = collateral_by_account
+                .get(&(borrow.account.id.as_str(), borrow.market.id.as_str()))
+                .copied();
+            compute_position_risk(borrow, collateral)
+        })
+        .collect();
+    sort_by_liquidation_proximity(&mut risks);
+    risks
+}
+
+/// Fetch every open borrow in a market and compute its risk, closest to liquidation first
+pub async fn fetch_market_risk(client: &GraphClient, market_id: &str) -> Result<Vec<PositionRisk>> {
+    let query = format!(
+        r#"
+    query MorphoMarketPositions($first: Int!, $lastId: ID!, $market: String!) {{
+        positions(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: {{ id_gt: $lastId, market: $market, side_in: [COLLATERAL, BORROWER], balance_gt: "0" }}
+        ) {{
+            ...PositionFields
+        }}
+    }}
+    {}"#,
+        POSITION_FIELDS
+    );
+
+    let variables = json!({
+        "market": market_id.to_lowercase(),
+    });
+
+    let positions: Vec<Position> = client
+        .query_paginated(&query, "positions", variables, None)
+        .await?;
+    Ok(risks_from_positions(positions))
+}
+
+/// Fetch the risk of a single account's borrow in a market, if it has one
+pub async fn fetch_position_risk(
+    client: &GraphClient,
+    market_id: &str,
+    account: &str,
+) -> Result<Option<PositionRisk>> {
+    let query = format!(
+        r#"
+    query MorphoAccountMarketPositions($first: Int!, $lastId: ID!, $market: String!, $account: String!) {{
+        positions(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: {{ id_gt: $lastId, market: $market, account: $account, side_in: [COLLATERAL, BORROWER], balance_gt: "0" }}
+        ) {{
+            ...PositionFields
+        }}
+    }}
+    {}"#,
+        POSITION_FIELDS
+    );
+
+    let variables = json!({
+        "market": market_id.to_lowercase(),
+        "account": account.to_lowercase(),
+    });
+
+    let positions: Vec<Position> = client
+        .query_paginated(&query, "positions", variables, None)
+        .await?;
+    Ok(risks_from_positions(positions).into_iter().next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::morpho::{EntityRef, PositionAsset, PositionMarket};
+
+    fn position(side: &str, asset: &str, balance: &str, decimals: i32, price: &str) -> Position {
+        Position {
+            id: format!("0xaccount-0xmarket-{}", side),
+            account: EntityRef {
+                id: "0xaccount".to_string(),
+            },
+            market: PositionMarket {
+                id: "0xmarket".to_string(),
+                name: "WETH/USDC".to_string(),
+                input_token: PositionAsset {
+                    id: "0xusdc".to_string(),
+                    symbol: "USDC".to_string(),
+                    decimals: 6,
+                    last_price_usd: Some("1".to_string()),
+                },
+                input_token_price_usd: "1".to_string(),
+                maximum_ltv: "86".to_string(),
+                liquidation_threshold: "86".to_string(),
+            },
+            asset: PositionAsset {
+                id: asset.to_string(),
+                symbol: asset.to_string(),
+                decimals,
+                last_price_usd: Some(price.to_string()),
+            },
+            side: side.to_string(),
+            is_collateral: Some(side == "COLLATERAL"),
+            balance: balance.to_string(),
+            principal: None,
+            shares: None,
+            timestamp_opened: "0".to_string(),
+            block_number_opened: "0".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_compute_position_risk() {
+        // 1 WETH at $2000 backing 860 USDC of debt
+        let collateral = position("COLLATERAL", "0xweth", "1000000000000000000", 18, "2000");
+        let borrow = position("BORROWER", "0xusdc", "860000000", 6, "1");
+
+        let risk = compute_position_risk(&borrow, Some(&collateral));
+
+        assert!((risk.ltv - 0.43).abs() < 1e-9);
+        assert!((risk.health_factor.unwrap() - 2.0).abs() < 1e-9);
+        assert!((risk.distance_to_liquidation.unwrap() - 0.5).abs() < 1e-9);
+        assert!((risk.liquidation_price.unwrap() - 1000.0).abs() < 1e-9);
+        assert!(!risk.is_liquidatable());
+    }
+
+    #[test]
+    fn test_uncollateralized_and_unpriced_borrows() {
+        let borrow = position("BORROWER", "0xusdc", "1000000", 6, "1");
+        let uncollateralized = compute_position_risk(&borrow, None);
+        assert_eq!(uncollateralized.health_factor, Some(0.0));
+        assert!(uncollateralized.liquidation_price.is_none());
+        assert!(uncollateralized.is_liquidatable());
+
+        let unpriced_collateral = position("COLLATERAL", "0xweth", "1000000000000000000", 18, "0");
+        let unpriced = compute_position_risk(&borrow, Some(&unpriced_collateral));
+        assert_eq!(unpriced.health_factor, None);
+        assert_eq!(unpriced.distance_to_liquidation, None);
+        assert!(!unpriced.is_liquidatable());
+
+        let mut risks = vec![unpriced, uncollateralized];
+        sort_by_liquidation_proximity(&mut risks);
+        assert_eq!(risks[0].health_factor, Some(0.0));
+        assert_eq!(risks[1].health_factor, None);
+    }
+}
@@ -0,0 +1,262 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{Decimal, InterestRate};
+use crate::client::GraphClient;
+use crate::numeric::{ratio, to_f64};
+
+/// Daily snapshot of a Morpho market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketDailySnapshot {
+    pub id: String,
+    pub days: i32,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    pub timestamp: String,
+    #[serde(rename = "inputTokenBalance")]
+    pub input_token_balance: String,
+    #[serde(rename = "inputTokenPriceUSD")]
+    pub input_token_price_usd: Decimal,
+    #[serde(default)]
+    pub rates: Option<Vec<InterestRate>>,
+    #[serde(rename = "totalValueLockedUSD")]
+    pub total_value_locked_usd: Decimal,
+    #[serde(rename = "totalDepositBalanceUSD")]
+    pub total_deposit_balance_usd: Decimal,
+    #[serde(rename = "totalBorrowBalanceUSD")]
+    pub total_borrow_balance_usd: Decimal,
+    #[serde(rename = "dailyDepositUSD")]
+    pub daily_deposit_usd: Decimal,
+    #[serde(rename = "dailyWithdrawUSD")]
+    pub daily_withdraw_usd: Decimal,
+    #[serde(rename = "dailyBorrowUSD")]
+    pub daily_borrow_usd: Decimal,
+    #[serde(rename = "dailyRepayUSD")]
+    pub daily_repay_usd: Decimal,
+    #[serde(rename = "dailyLiquidateUSD")]
+    pub daily_liquidate_usd: Decimal,
+    #[serde(rename = "cumulativeSupplySideRevenueUSD")]
+    pub cumulative_supply_side_revenue_usd: Decimal,
+    #[serde(rename = "cumulativeProtocolSideRevenueUSD")]
+    pub cumulative_protocol_side_revenue_usd: Decimal,
+    #[serde(rename = "cumulativeTotalRevenueUSD")]
+    pub cumulative_total_revenue_usd: Decimal,
+    #[serde(rename = "dailySupplySideRevenueUSD")]
+    pub daily_supply_side_revenue_usd: Decimal,
+    #[serde(rename = "dailyProtocolSideRevenueUSD")]
+    pub daily_protocol_side_revenue_usd: Decimal,
+    #[serde(rename = "dailyTotalRevenueUSD")]
+    pub daily_total_revenue_usd: Decimal,
+    #[serde(rename = "dailyActiveUsers")]
+    pub daily_active_users: i32,
+    #[serde(rename = "dailyActiveDepositors")]
+    pub daily_active_depositors: i32,
+    #[serde(rename = "dailyActiveBorrowers")]
+    pub daily_active_borrowers: i32,
+    #[serde(rename = "openPositionCount")]
+    pub open_position_count: i32,
+}
+
+/// Hourly snapshot of a Morpho market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketHourlySnapshot {
+    pub id: String,
+    pub hours: i32,
+    #[serde(rename = "blockNumber")]
+    pub block_number: String,
+    pub timestamp: String,
+    #[serde(rename = "inputTokenBalance")]
+    pub input_token_balance: String,
+    #[serde(rename = "inputTokenPriceUSD")]
+    pub input_token_price_usd: Decimal,
+    #[serde(default)]
+    pub rates: Option<Vec<InterestRate>>,
+    #[serde(rename = "totalValueLockedUSD")]
+    pub total_value_locked_usd: Decimal,
+    #[serde(rename = "totalDepositBalanceUSD")]
+    pub total_deposit_balance_usd: Decimal,
+    #[serde(rename = "totalBorrowBalanceUSD")]
+    pub total_borrow_balance_usd: Decimal,
+    #[serde(rename = "hourlyDepositUSD")]
+    pub hourly_deposit_usd: Decimal,
+    #[serde(rename = "hourlyWithdrawUSD")]
+    pub hourly_withdraw_usd: Decimal,
+    #[serde(rename = "hourlyBorrowUSD")]
+    pub hourly_borrow_usd: Decimal,
+    #[serde(rename = "hourlyRepayUSD")]
+    pub hourly_repay_usd: Decimal,
+    #[serde(rename = "hourlyLiquidateUSD")]
+    pub hourly_liquidate_usd: Decimal,
+    #[serde(rename = "hourlySupplySideRevenueUSD")]
+    pub hourly_supply_side_revenue_usd: Decimal,
+    #[serde(rename = "hourlyProtocolSideRevenueUSD")]
+    pub hourly_protocol_side_revenue_usd: Decimal,
+    #[serde(rename = "hourlyTotalRevenueUSD")]
+    pub hourly_total_revenue_usd: Decimal,
+}
+
+impl MarketDailySnapshot {
+    /// Share of deposits that is currently borrowed, between 0 and 1
+    pub fn utilization(&self) -> f64 {
+        ratio(
+            to_f64(&self.total_borrow_balance_usd),
+            to_f64(&self.total_deposit_balance_usd),
+        )
+    }
+
+    /// Lender rate at the time of the snapshot, in percent
+    pub fn supply_rate(&self) -> Option<f64> {
+        side_rate(self.rates.as_deref(), "LENDER")
+    }
+
+    /// Borrower rate at the time of the snapshot, in percent
+    pub fn borrow_rate(&self) -> Option<f64> {
+        side_rate(self.rates.as_deref(), "BORROWER")
+    }
+}
+
+impl MarketHourlySnapshot {
+    /// Share of deposits that is currently borrowed, between 0 and 1
+    pub fn utilization(&self) -> f64 {
+        ratio(
+            to_f64(&self.total_borrow_balance_usd),
+            to_f64(&self.total_deposit_balance_usd),
+        )
+    }
+
+    /// Lender rate at the time of the snapshot, in percent
+    pub fn supply_rate(&self) -> Option<f64> {
+        side_rate(self.rates.as_deref(), "LENDER")
+    }
+
+    /// Borrower rate at the time of the snapshot, in percent
+    pub fn borrow_rate(&self) -> Option<f64> {
+        side_rate(self.rates.as_deref(), "BORROWER")
+    }
+}
+
+/// Rate of `side` (`LENDER` or `BORROWER`) in percent, if the market reports one
+pub(crate) fn side_rate(rates: Option<&[InterestRate]>, side: &str) -> Option<f64> {
+    rates?
+        .iter()
+        .find(|rate| rate.side == side)
+        .map(|rate| to_f64(&rate.rate))
+}
+
+/// Fetch the daily snapshots of a market with a timestamp in `[from, to)`, oldest first
+pub async fn fetch_market_daily_snapshots(
+    client: &GraphClient,
+    market_id: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<MarketDailySnapshot>> {
+    let query = r#"
+    query MorphoMarketDailySnapshots($first: Int!, $lastId: Bytes!, $market: String!, $from: BigInt!, $to: BigInt!) {
+        marketDailySnapshots(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, market: $market, timestamp_gte: $from, timestamp_lt: $to }
+        ) {
+            id
+            days
+            blockNumber
+            timestamp
+            inputTokenBalance
+            inputTokenPriceUSD
+            rates {
+                id
+                rate
+                side
+                type
+            }
+            totalValueLockedUSD
+            totalDepositBalanceUSD
+            totalBorrowBalanceUSD
+            dailyDepositUSD
+            dailyWithdrawUSD
+            dailyBorrowUSD
+            dailyRepayUSD
+            dailyLiquidateUSD
+            cumulativeSupplySideRevenueUSD
+            cumulativeProtocolSideRevenueUSD
+            cumulativeTotalRevenueUSD
+            dailySupplySideRevenueUSD
+            dailyProtocolSideRevenueUSD
+            dailyTotalRevenueUSD
+            dailyActiveUsers
+            dailyActiveDepositors
+            dailyActiveBorrowers
+            openPositionCount
+        }
+    }
+    "#;
+
+    let variables = json!({
+        "market": market_id.to_lowercase(),
+        "from": from.to_string(),
+        "to": to.to_string(),
+    });
+
+    let mut snapshots: Vec<MarketDailySnapshot> = client
+        .query_paginated(query, "marketDailySnapshots", variables, None)
+        .await?;
+    snapshots.sort_by_key(|snapshot| snapshot.days);
+    Ok(snapshots)
+}
+
+/// Fetch the hourly snapshots of a market with a timestamp in `[from, to)`, oldest first
+pub async fn fetch_market_hourly_snapshots(
+    client: &GraphClient,
+    market_id: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<MarketHourlySnapshot>> {
+    let query = r#"
+    query MorphoMarketHourlySnapshots($first: Int!, $lastId: Bytes!, $market: String!, $from: BigInt!, $to: BigInt!) {
+        marketHourlySnapshots(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, market: $market, timestamp_gte: $from, timestamp_lt: $to }
+        ) {
+            id
+            hours
+            blockNumber
+            timestamp
+            inputTokenBalance
+            inputTokenPriceUSD
+            rates {
+                id
+                rate
+                side
+                type
+            }
+            totalValueLockedUSD
+            totalDepositBalanceUSD
+            totalBorrowBalanceUSD
+            hourlyDepositUSD
+            hourlyWithdrawUSD
+            hourlyBorrowUSD
+            hourlyRepayUSD
+            hourlyLiquidateUSD
+            hourlySupplySideRevenueUSD
+            hourlyProtocolSideRevenueUSD
+            hourlyTotalRevenueUSD
+        }
+    }
+    "#;
+
+    let variables = json!({
+        "market": market_id.to_lowercase(),
+        "from": from.to_string(),
+        "to": to.to_string(),
+    });
+
+    let mut snapshots: Vec<MarketHourlySnapshot> = client
+        .query_paginated(query, "marketHourlySnapshots", variables, None)
+        .await?;
+    snapshots.sort_by_key(|snapshot| snapshot.hours);
+    Ok(snapshots)
+}
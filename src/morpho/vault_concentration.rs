@@ -0,0 +1,335 @@
+//! MetaMorpho depositor concentration and flows, for assessing withdrawal-run risk.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::vaults::{fetch_vault, MetaMorphoVault};
+use super::{Decimal, EntityRef};
+use crate::client::GraphClient;
+use crate::numeric::{ratio, to_f64};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Controls how much detail [`fetch_vault_concentration`] returns
+#[derive(Debug, Clone)]
+pub struct ConcentrationOptions {
+    /// Number of largest holders to return
+    pub top_holders: usize,
+    /// Number of largest withdrawals to return
+    pub largest_withdrawals: usize,
+    /// Days of deposit and withdraw history to aggregate
+    pub lookback_days: i64,
+}
+
+impl Default for ConcentrationOptions {
+    fn default() -> Self {
+        ConcentrationOptions {
+            top_holders: 10,
+            largest_withdrawals: 10,
+            lookback_days: 30,
+        }
+    }
+}
+
+/// An account holding vault shares
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultHolder {
+    pub account: String,
+    pub shares: String,
+    /// Share of the vault's total supply, between 0 and 1
+    pub share_of_supply: f64,
+    /// Value of the shares in whole units of the vault asset
+    pub assets: f64,
+}
+
+/// Deposits and withdrawals of a vault during one day
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DailyFlow {
+    /// Start of the day, in seconds since the Unix epoch
+    pub day: i64,
+    /// Deposited assets, in whole units
+    pub inflow: f64,
+    /// Withdrawn assets, in whole units
+    pub outflow: f64,
+    pub net: f64,
+    pub inflow_usd: f64,
+    pub outflow_usd: f64,
+    pub net_usd: f64,
+}
+
+/// A single deposit or withdrawal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultFlow {
+    pub id: String,
+    pub hash: String,
+    pub account: EntityRef,
+    pub timestamp: String,
+    /// Assets moved, in native units
+    pub amount: String,
+    #[serde(rename = "amountUSD")]
+    pub amount_usd: Decimal,
+}
+
+/// Holder concentration, flows and liquidity of a MetaMorpho vault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultConcentration {
+    pub vault_id: String,
+    pub name: String,
+    pub asset_symbol: String,
+    pub holder_count: usize,
+    /// Largest holders first
+    pub top_holders: Vec<VaultHolder>,
+    /// Sum of squared supply shares over all holders, 1 when a single account holds everything
+    pub herfindahl_index: f64,
+    /// Oldest day first
+    pub daily_flows: Vec<DailyFlow>,
+    /// Largest first
+    pub largest_withdrawals: Vec<VaultFlow>,
+    /// Assets not allocated to any market, in whole units
+    pub idle: f64,
+    /// Assets of the largest holder, in whole units
+    pub top_holder_assets: f64,
+    /// Idle assets divided by the largest holder's assets; below 1 the top holder cannot exit
+    /// without the vault withdrawing from markets
+    pub idle_coverage: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct HolderPosition {
+    account: EntityRef,
+    shares: String,
+}
+
+/// Fetch the holders and recent flows of a vault and measure its concentration
+pub async fn fetch_vault_concentration(
+    client: &GraphClient,
+    vault: &str,
+    options: &ConcentrationOptions,
+) -> Result<VaultConcentration> {
+    let vault = fetch_vault(client, vault).await?;
+
+    let holders_query = r#"
+    query MorphoVaultHolders($first: Int!, $lastId: Bytes!, $vault: String!) {
+        metaMorphoPositions(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, metaMorpho: $vault, shares_gt: "0" }
+        ) {
+            id
+            account {
+                id
+            }
+            shares
+        }
+    }
+    "#;
+    let deposits_query = r#"
+    query MorphoVaultDeposits($first: Int!, $lastId: Bytes!, $vault: String!, $from: BigInt!) {
+        metaMorphoDeposits(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, metaMorpho: $vault, timestamp_gte: $from }
+        ) {
+            id
+            hash
+            account {
+                id
+            }
+            timestamp
+            amount
+            amountUSD
+        }
+    }
+    "#;
+    let withdraws_query = r#"
+    query MorphoVaultWithdraws($first: Int!, $lastId: Bytes!, $vault: String!, $from: BigInt!) {
+        metaMorphoWithdraws(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, metaMorpho: $vault, timestamp_gte: $from }
+        ) {
+            id
+            hash
+            account {
+                id
+            }
+            timestamp
+            amount
+            amountUSD
+        }
+    }
+    "#;
+
+    let from = chrono::Utc::now().timestamp() - options.lookback_days * SECONDS_PER_DAY;
+    let variables = json!({
+        "vault": vault.id.to_lowercase(),
+        "from": from.to_string(),
+    });
+
+    let positions: Vec<HolderPosition> = client
+        .query_paginated(
+            holders_query,
+            "metaMorphoPositions",
+            variables.clone(),
+            None,
+        )
+        .await?;
+    let deposits: Vec<VaultFlow> = client
+        .query_paginated(
+            deposits_query,
+            "metaMorphoDeposits",
+            variables.clone(),
+            None,
+        )
+        .await?;
+    let withdraws: Vec<VaultFlow> = client
+        .query_paginated(withdraws_query, "metaMorphoWithdraws", variables, None)
+        .await?;
+
+    Ok(build_concentration(
+        vault, positions, &deposits, withdraws, options,
+    ))
+}
+
+fn build_concentration(
+    vault: MetaMorphoVault,
+    positions: Vec<HolderPosition>,
+    deposits: &[VaultFlow],
+    mut withdraws: Vec<VaultFlow>,
+    options: &ConcentrationOptions,
+) -> VaultConcentration {
+    let scale = 10f64.powi(vault.asset.decimals);
+    let total_shares = to_f64(&vault.total_shares);
+    let share_price = vault.share_price();
+
+    let mut holders: Vec<VaultHolder> = positions
+        .into_iter()
+        .map(|position| {
+            let shares = to_f64(&position.shares);
+            VaultHolder {
+                account: position.account.id,
+                share_of_supply: ratio(shares, total_shares),
+                assets: shares * share_price / scale,
+                shares: position.shares,
+            }
+        })
+        .collect();
+    holders.sort_by(|a, b| b.share_of_supply.total_cmp(&a.share_of_supply));
+
+    let herfindahl_index = herfindahl_index(
+        &holders
+            .iter()
+            .map(|h| h.share_of_supply)
+            .collect::<Vec<_>>(),
+    );
+    let daily_flows = daily_net_flows(deposits, &withdraws, scale);
+
+    withdraws.sort_by(|a, b| to_f64(&b.amount).total_cmp(&to_f64(&a.amount)));
+    withdraws.truncate(options.largest_withdrawals);
+
+    let idle = to_f64(&vault.idle) / scale;
+    let top_holder_assets = holders.first().map(|h| h.assets).unwrap_or(0.0);
+    let holder_count = holders.len();
+    holders.truncate(options.top_holders);
+
+    VaultConcentration {
+        vault_id: vault.id,
+        name: vault.name,
+        asset_symbol: vault.asset.symbol,
+        holder_count,
+        top_holders: holders,
+        herfindahl_index,
+        daily_flows,
+        largest_withdrawals: withdraws,
+        idle,
+        top_holder_assets,
+        idle_coverage: ratio(idle, top_holder_assets),
+    }
+}
+
+/// Herfindahl-Hirschman index of a set of market shares, each between 0 and 1
+pub fn herfindahl_index(shares: &[f64]) -> f64 {
+    shares.iter().map(|share| share * share).sum()
+}
+
+/// Aggregate deposits and withdrawals per day, oldest first. `scale` converts native amounts
+/// into whole units.
+pub fn daily_net_flows(
+    deposits: &[VaultFlow],
+    withdraws: &[VaultFlow],
+    scale: f64,
+) -> Vec<DailyFlow> {
+    let mut days: BTreeMap<i64, DailyFlow> = BTreeMap::new();
+    for deposit in deposits {
+        let day = day_entry(&mut days, deposit);
+        day.inflow += to_f64(&deposit.amount) / scale;
+        day.inflow_usd += to_f64(&deposit.amount_usd);
+    }
+    for withdraw in withdraws {
+        let day = day_entry(&mut days, withdraw);
+        day.outflow += to_f64(&withdraw.amount) / scale;
+        day.outflow_usd += to_f64(&withdraw.amount_usd);
+    }
+
+    days.into_values()
+        .map(|mut flow| {
+            flow.net = flow.inflow - flow.outflow;
+            flow.net_usd = flow.inflow_usd - flow.outflow_usd;
+            flow
+        })
+        .collect()
+}
+
+fn day_entry<'a>(days: &'a mut BTreeMap<i64, DailyFlow>, flow: &VaultFlow) -> &'a mut DailyFlow {
+    let day = flow.timestamp.parse::<i64>().unwrap_or(0) / SECONDS_PER_DAY * SECONDS_PER_DAY;
+    days.entry(day).or_insert_with(|| DailyFlow {
+        day,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow(day: i64, amount: &str, amount_usd: &str) -> VaultFlow {
+        VaultFlow {
+            id: format!("0x{}-{}", day, amount),
+            hash: "0xhash".to_string(),
+            account: EntityRef {
+                id: "0xaccount".to_string(),
+            },
+            timestamp: (day * SECONDS_PER_DAY + 3600).to_string(),
+            amount: amount.to_string(),
+            amount_usd: amount_usd.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_herfindahl_index() {
+        assert_eq!(herfindahl_index(&[1.0]), 1.0);
+        assert!((herfindahl_index(&[0.5, 0.25, 0.25]) - 0.375).abs() < 1e-12);
+        assert_eq!(herfindahl_index(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_daily_net_flows() {
+        let deposits = vec![flow(1, "3000000", "3"), flow(1, "2000000", "2")];
+        let withdraws = vec![flow(1, "1000000", "1"), flow(2, "4000000", "4")];
+
+        let flows = daily_net_flows(&deposits, &withdraws, 1e6);
+
+        assert_eq!(flows.len(), 2);
+        assert_eq!(flows[0].day, SECONDS_PER_DAY);
+        assert_eq!(flows[0].net, 4.0);
+        assert_eq!(flows[0].net_usd, 4.0);
+        assert_eq!(flows[1].outflow, 4.0);
+        assert_eq!(flows[1].net, -4.0);
+    }
+}
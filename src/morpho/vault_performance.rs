@@ -0,0 +1,300 @@
+//! MetaMorpho share price history and realized returns.
+//!
+//! Every deposit and withdraw converts between assets and shares at the vault's share price,
+//! so `amount / shares` of those events traces the share price over time. Performance fees are
+//! minted as new shares, which means share price growth is already net of the vault `fee`.
+//! Realized APY over a window is `(p1 / p0) ^ (1 year / elapsed) - 1`.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::vaults::{fetch_vault, fetch_vaults, MetaMorphoVault};
+use super::GraphQLResponse;
+use crate::client::GraphClient;
+use crate::numeric::to_f64;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+const SECONDS_PER_YEAR: f64 = 365.0 * SECONDS_PER_DAY as f64;
+
+/// Windows, in days, over which realized APY is reported
+pub const REALIZED_WINDOWS: [i64; 3] = [7, 30, 90];
+
+/// Share price of a vault at a point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharePricePoint {
+    pub timestamp: i64,
+    pub block_number: String,
+    /// Assets per share, in whole units of each
+    pub share_price: f64,
+}
+
+/// Realized APY over a trailing window compared with the vault's quoted rate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RealizedReturn {
+    pub window_days: i64,
+    /// Net of fee, in percent
+    pub apy: f64,
+    /// Realized APY minus the quoted rate net of fee, in percent
+    pub gap_to_quoted: f64,
+}
+
+/// Performance summary of a MetaMorpho vault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultPerformance {
+    pub vault_id: String,
+    pub name: String,
+    pub asset_symbol: String,
+    /// Performance fee, between 0 and 1
+    pub fee: f64,
+    /// Rate reported by the subgraph, in percent
+    pub quoted_apy: f64,
+    /// Quoted rate after the performance fee, in percent
+    pub quoted_net_apy: f64,
+    /// Current assets per share, in whole units of each
+    pub share_price: f64,
+    /// One entry per window in [`REALIZED_WINDOWS`] that the series covers
+    pub realized: Vec<RealizedReturn>,
+    /// Oldest first, ending with the current share price
+    pub series: Vec<SharePricePoint>,
+}
+
+impl VaultPerformance {
+    /// Realized return over a window of `days`, if the series covers it
+    pub fn realized(&self, days: i64) -> Option<&RealizedReturn> {
+        self.realized.iter().find(|r| r.window_days == days)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ShareConversion {
+    timestamp: String,
+    #[serde(rename = "blockNumber")]
+    block_number: String,
+    #[serde(rename = "logIndex")]
+    log_index: i32,
+    amount: String,
+    shares: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct AnchorResponse {
+    #[serde(rename = "metaMorphoDeposits")]
+    meta_morpho_deposits: Vec<ShareConversion>,
+}
+
+/// Fetch the share price implied by every deposit and withdraw of a vault since `from`,
+/// oldest first. The last deposit before `from` is included so that a window starting at
+/// `from` has a starting price.
+pub async fn fetch_share_price_series(
+    client: &GraphClient,
+    vault: &MetaMorphoVault,
+    from: i64,
+) -> Result<Vec<SharePricePoint>> {
+    let deposits_query = r#"
+    query MorphoVaultDepositPrices($first: Int!, $lastId: Bytes!, $vault: String!, $from: BigInt!) {
+        metaMorphoDeposits(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, metaMorpho: $vault, timestamp_gte: $from, shares_gt: "0" }
+        ) {
+            id
+            timestamp
+            blockNumber
+            logIndex
+            amount
+            shares
+        }
+    }
+    "#;
+    let withdraws_query = r#"
+    query MorphoVaultWithdrawPrices($first: Int!, $lastId: Bytes!, $vault: String!, $from: BigInt!) {
+        metaMorphoWithdraws(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, metaMorpho: $vault, timestamp_gte: $from, shares_gt: "0" }
+        ) {
+            id
+            timestamp
+            blockNumber
+            logIndex
+            amount
+            shares
+        }
+    }
+    "#;
+
+    let anchor_query = r#"
+    query MorphoVaultAnchorPrice($vault: String!, $from: BigInt!) {
+        metaMorphoDeposits(
+            first: 1,
+            orderBy: timestamp,
+            orderDirection: desc,
+            where: { metaMorpho: $vault, timestamp_lt: $from, shares_gt: "0" }
+        ) {
+            id
+            timestamp
+            blockNumber
+            logIndex
+            amount
+            shares
+        }
+    }
+    "#;
+
+    let variables = json!({
+        "vault": vault.id.to_lowercase(),
+        "from": from.to_string(),
+    });
+
+    let anchor: GraphQLResponse<AnchorResponse> =
+        client.query_raw(anchor_query, variables.clone()).await?;
+    let mut conversions = anchor.data.meta_morpho_deposits;
+    conversions.extend(
+        client
+            .query_paginated::<ShareConversion>(
+                deposits_query,
+                "metaMorphoDeposits",
+                variables.clone(),
+                None,
+            )
+            .await?,
+    );
+    conversions.extend(
+        client
+            .query_paginated::<ShareConversion>(
+                withdraws_query,
+                "metaMorphoWithdraws",
+                variables,
+                None,
+            )
+            .await?,
+    );
+    conversions.sort_by_key(|c| (c.timestamp.parse::<i64>().unwrap_or(0), c.log_index));
+
+    let scale = share_scale(vault);
+    Ok(conversions
+        .into_iter()
+        .map(|c| SharePricePoint {
+            timestamp: c.timestamp.parse().unwrap_or(0),
+            block_number: c.block_number,
+            share_price: to_f64(&c.amount) / to_f64(&c.shares) * scale,
+        })
+        .collect())
+}
+
+/// Factor converting a native assets-per-share ratio into whole units
+fn share_scale(vault: &MetaMorphoVault) -> f64 {
+    10f64.powi(vault.decimals - vault.asset.decimals)
+}
+
+/// Fetch a vault's share price series over the longest window and compute its realized returns
+pub async fn fetch_vault_performance(
+    client: &GraphClient,
+    vault: &str,
+) -> Result<VaultPerformance> {
+    let vault = fetch_vault(client, vault).await?;
+    performance_of(client, vault).await
+}
+
+/// Fetch the performance of the largest `limit` vaults, best 30 day realized APY first
+pub async fn rank_vaults_by_performance(
+    client: &GraphClient,
+    limit: i64,
+) -> Result<Vec<VaultPerformance>> {
+    let mut performances = Vec::new();
+    for vault in fetch_vaults(client, limit).await? {
+        performances.push(performance_of(client, vault).await?);
+    }
+
+    let apy_30d = |p: &VaultPerformance| p.realized(30).map(|r| r.apy).unwrap_or(f64::MIN);
+    performances.sort_by(|a, b| apy_30d(b).total_cmp(&apy_30d(a)));
+    Ok(performances)
+}
+
+async fn performance_of(client: &GraphClient, vault: MetaMorphoVault) -> Result<VaultPerformance> {
+    let now = chrono::Utc::now().timestamp();
+    let longest = REALIZED_WINDOWS[REALIZED_WINDOWS.len() - 1];
+    let mut series =
+        fetch_share_price_series(client, &vault, now - longest * SECONDS_PER_DAY).await?;
+
+    let share_price = vault.share_price() * share_scale(&vault);
+    series.push(SharePricePoint {
+        timestamp: now,
+        block_number: "latest".to_string(),
+        share_price,
+    });
+
+    let fee = vault.fee_rate();
+    let quoted_apy = to_f64(&vault.rate.rate);
+    let quoted_net_apy = quoted_apy * (1.0 - fee);
+    let realized = REALIZED_WINDOWS
+        .iter()
+        .filter_map(|&days| {
+            realized_apy(&series, days).map(|apy| RealizedReturn {
+                window_days: days,
+                apy,
+                gap_to_quoted: apy - quoted_net_apy,
+            })
+        })
+        .collect();
+
+    Ok(VaultPerformance {
+        vault_id: vault.id,
+        name: vault.name,
+        asset_symbol: vault.asset.symbol,
+        fee,
+        quoted_apy,
+        quoted_net_apy,
+        share_price,
+        realized,
+        series,
+    })
+}
+
+/// Annualized share price growth over the trailing `days` of a series, in percent. The start is
+/// the last point at or before the window start; returns `None` if the series does not reach
+/// that far back.
+pub fn realized_apy(series: &[SharePricePoint], days: i64) -> Option<f64> {
+    let end = series.last()?;
+    let window_start = end.timestamp - days * SECONDS_PER_DAY;
+    let start = series.iter().rev().find(|p| p.timestamp <= window_start)?;
+
+    let elapsed = (end.timestamp - start.timestamp) as f64;
+    if elapsed <= 0.0 || start.share_price <= 0.0 {
+        return None;
+    }
+    let growth = end.share_price / start.share_price;
+    Some((growth.powf(SECONDS_PER_YEAR / elapsed) - 1.0) * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(day: i64, share_price: f64) -> SharePricePoint {
+        SharePricePoint {
+            timestamp: day * SECONDS_PER_DAY,
+            block_number: day.to_string(),
+            share_price,
+        }
+    }
+
+    #[test]
+    fn test_realized_apy() {
+        let growth_30d = 1.05f64.powf(30.0 / 365.0);
+        let series = vec![
+            point(0, 1.0),
+            point(100, 1.1),
+            point(110, 1.1),
+            point(140, 1.1 * growth_30d),
+        ];
+
+        let apy = realized_apy(&series, 30).unwrap();
+        assert!((apy - 5.0).abs() < 1e-9);
+        // The series does not reach back a full year
+        assert!(realized_apy(&series, 365).is_none());
+    }
+}
@@ -0,0 +1,445 @@
+//! MetaMorpho vaults: listing, fee configuration, queues and per-market allocation.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{Decimal, EntityRef, GraphQLResponse, InterestRate, Token, TokenRef};
+use crate::client::{GraphClient, MAX_PAGE_SIZE};
+use crate::numeric::{ratio, to_f64};
+
+/// Scale of MetaMorpho fees, which are stored as WAD fractions
+const WAD: f64 = 1e18;
+
+/// Fields selected for every MetaMorpho vault
+pub(crate) const VAULT_FIELDS: &str = r#"
+    fragment VaultFields on MetaMorpho {
+        id
+        version
+        name
+        symbol
+        decimals
+        asset {
+            name
+            symbol
+            decimals
+        }
+        owner {
+            id
+        }
+        curator {
+            id
+        }
+        guardian {
+            id
+        }
+        timelock
+        fee
+        feeRecipient {
+            account {
+                id
+            }
+        }
+        feeAccrued
+        rate {
+            id
+            rate
+            side
+            type
+        }
+        lastTotalAssets
+        totalShares
+        idle
+        hasPublicAllocator
+    }
+"#;
+
+/// A MetaMorpho vault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetaMorphoVault {
+    pub id: String,
+    pub version: String,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: i32,
+    pub asset: Token,
+    pub owner: EntityRef,
+    pub curator: Option<EntityRef>,
+    pub guardian: Option<EntityRef>,
+    pub timelock: String,
+    pub fee: String,
+    #[serde(rename = "feeRecipient")]
+    pub fee_recipient: Option<FeeRecipientRef>,
+    #[serde(rename = "feeAccrued")]
+    pub fee_accrued: String,
+    pub rate: InterestRate,
+    #[serde(rename = "lastTotalAssets")]
+    pub last_total_assets: String,
+    #[serde(rename = "totalShares")]
+    pub total_shares: String,
+    pub idle: String,
+    #[serde(rename = "hasPublicAllocator")]
+    pub has_public_allocator: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeRecipientRef {
+    pub account: EntityRef,
+}
+
+impl MetaMorphoVault {
+    /// Performance fee as a fraction of interest, between 0 and 1
+    pub fn fee_rate(&self) -> f64 {
+        to_f64(&self.fee) / WAD
+    }
+
+    /// Assets held by the vault per share, in native units
+    pub fn share_price(&self) -> f64 {
+        ratio(to_f64(&self.last_total_assets), to_f64(&self.total_shares))
+    }
+}
+
+/// Allocation of a vault into a single Morpho Blue market
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketAllocation {
+    /// Id of the `MetaMorphoMarket` entity
+    pub id: String,
+    pub market_id: String,
+    pub market_name: String,
+    pub loan_token: String,
+    /// Supply cap in native units of the vault asset
+    pub cap: Decimal,
+    pub enabled: bool,
+    pub removable_at: String,
+    /// Assets currently supplied to the market, in native units
+    pub supplied_assets: Decimal,
+    /// Share of the vault's total assets supplied to this market, between 0 and 1
+    pub share_of_vault: f64,
+    /// Share of the cap already used, between 0 and 1
+    pub cap_usage: f64,
+    /// Position in the supply queue, if the market is in it
+    pub supply_queue_index: Option<usize>,
+    /// Position in the withdraw queue, if the market is in it
+    pub withdraw_queue_index: Option<usize>,
+}
+
+/// A vault together with its allocation across markets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultAllocation {
+    pub vault: MetaMorphoVault,
+    /// Market allocations, in withdraw queue order followed by markets outside the queue
+    pub markets: Vec<MarketAllocation>,
+    /// Market ids in supply queue order
+    pub supply_queue: Vec<String>,
+    /// Market ids in withdraw queue order
+    pub withdraw_queue: Vec<String>,
+    /// Share of the vault's total assets left idle, between 0 and 1
+    pub idle_share: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct QueueEntry {
+    id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct VaultMarketRef {
+    id: String,
+    name: String,
+    #[serde(rename = "inputToken")]
+    input_token: TokenRef,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct VaultMarket {
+    id: String,
+    cap: String,
+    enabled: bool,
+    #[serde(rename = "removableAt")]
+    removable_at: String,
+    market: VaultMarketRef,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct VaultWithMarkets {
+    #[serde(flatten)]
+    vault: MetaMorphoVault,
+    #[serde(rename = "supplyQueue")]
+    supply_queue: Vec<QueueEntry>,
+    #[serde(rename = "withdrawQueue")]
+    withdraw_queue: Vec<QueueEntry>,
+    markets: Vec<VaultMarket>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct SupplyPosition {
+    balance: String,
+    market: EntityRef,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct VaultAllocationResponse {
+    #[serde(rename = "metaMorpho")]
+    meta_morpho: Option<VaultWithMarkets>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct VaultsResponse {
+    #[serde(rename = "metaMorphos")]
+    meta_morphos: Vec<MetaMorphoVault>,
+}
+
+/// Fetch MetaMorpho vaults, ordered by total assets
+pub async fn fetch_vaults(client: &GraphClient, limit: i64) -> Result<Vec<MetaMorphoVault>> {
+    let query = format!(
+        r#"
+    query MorphoVaults($first: Int) {{
+        metaMorphos(first: $first, orderBy: lastTotalAssets, orderDirection: desc) {{
+            ...VaultFields
+        }}
+    }}
+    {}"#,
+        VAULT_FIELDS
+    );
+
+    let variables = json!({
+        "first": limit,
+    });
+
+    let response: GraphQLResponse<VaultsResponse> = client.query_raw(&query, variables).await?;
+    Ok(response.data.meta_morphos)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct VaultResponse {
+    #[serde(rename = "metaMorpho")]
+    meta_morpho: Option<MetaMorphoVault>,
+}
+
+/// Fetch a single MetaMorpho vault by address
+pub async fn fetch_vault(client: &GraphClient, vault: &str) -> Result<MetaMorphoVault> {
+    let query = format!(
+        r#"
+    query MorphoVault($id: ID!) {{
+        metaMorpho(id: $id) {{
+            ...VaultFields
+        }}
+    }}
+    {}"#,
+        VAULT_FIELDS
+    );
+
+    let vault = vault.to_lowercase();
+    let variables = json!({
+        "id": vault,
+    });
+
+    let response: GraphQLResponse<VaultResponse> = client.query_raw(&query, variables).await?;
+    response
+        .data
+        .meta_morpho
+        .ok_or_else(|| anyhow!("MetaMorpho vault {} not found", vault))
+}
+
+/// Fetch a vault's full allocation across markets, with caps, queues and fees
+pub async fn fetch_vault_allocation(client: &GraphClient, vault: &str) -> Result<VaultAllocation> {
+    let query = format!(
+        r#"
+    query MorphoVaultAllocation($id: ID!, $first: Int) {{
+        metaMorpho(id: $id) {{
+            ...VaultFields
+            supplyQueue {{
+                id
+            }}
+            withdrawQueue {{
+                id
+            }}
+            markets(first: $first) {{
+                id
+                cap
+                enabled
+                removableAt
+                market {{
+                    id
+                    name
+                    inputToken {{
+                        symbol
+                    }}
+                }}
+            }}
+        }}
+    }}
+    {}"#,
+        VAULT_FIELDS
+    );
+    let positions_query = r#"
+    query MorphoVaultAllocationPositions($first: Int!, $lastId: ID!, $account: String!) {
+        positions(
+            first: $first,
+            orderBy: id,
+            orderDirection: asc,
+            where: { id_gt: $lastId, account: $account, side: SUPPLIER, balance_gt: "0" }
+        ) {
+            id
+            balance
+            market {
+                id
+            }
+        }
+    }
+    "#;
+
+    let vault = vault.to_lowercase();
+    let variables = json!({
+        "id": vault,
+        "first": MAX_PAGE_SIZE,
+    });
+
+    let response: GraphQLResponse<VaultAllocationResponse> =
+        client.query_raw(&query, variables).await?;
+    let vault_data = response
+        .data
+        .meta_morpho
+        .ok_or_else(|| anyhow!("MetaMorpho vault {} not found", vault))?;
+    let positions: Vec<SupplyPosition> = client
+        .query_paginated(
+            positions_query,
+            "positions",
+            json!({ "account": vault }),
+            None,
+        )
+        .await?;
+
+    Ok(build_allocation(vault_data, &positions))
+}
+
+fn build_allocation(data: VaultWithMarkets, positions: &[SupplyPosition]) -> VaultAllocation {
+    let total_assets = to_f64(&data.vault.last_total_assets);
+    let supply_queue: Vec<String> = data.supply_queue.iter().map(|q| q.id.clone()).collect();
+    let withdraw_queue: Vec<String> = data.withdraw_queue.iter().map(|q| q.id.clone()).collect();
+
+    let mut markets: Vec<MarketAllocation> = data
+        .markets
+        .into_iter()
+        .map(|entry| {
+            let supplied_assets = positions
+                .iter()
+                .find(|position| position.market.id == entry.market.id)
+                .map(|position| position.balance.clone())
+                .unwrap_or_else(|| "0".to_string());
+            let supplied = to_f64(&supplied_assets);
+
+            MarketAllocation {
+                supply_queue_index: supply_queue.iter().position(|id| *id == entry.id),
+                withdraw_queue_index: withdraw_queue.iter().position(|id| *id == entry.id),
+                share_of_vault: ratio(supplied, total_assets),
+                cap_usage: ratio(supplied, to_f64(&entry.cap)),
+                id: entry.id,
+                market_id: entry.market.id,
+                market_name: entry.market.name,
+                loan_token: entry.market.input_token.symbol,
+                cap: entry.cap,
+                enabled: entry.enabled,
+                removable_at: entry.removable_at,
+                supplied_assets,
+            }
+        })
+        .collect();
+    markets.sort_by_key(|market| market.withdraw_queue_index.unwrap_or(usize::MAX));
+
+    let market_id_of = |queue: &[String]| -> Vec<String> {
+        queue
+            .iter()
+            .filter_map(|id| markets.iter().find(|m| m.id == *id))
+            .map(|m| m.market_id.clone())
+            .collect()
+    };
+    let supply_queue = market_id_of(&supply_queue);
+    let withdraw_queue = market_id_of(&withdraw_queue);
+
+    VaultAllocation {
+        idle_share: ratio(to_f64(&data.vault.idle), total_assets),
+        vault: data.vault,
+        markets,
+        supply_queue,
+        withdraw_queue,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vault_market(id: &str, market: &str, cap: &str) -> serde_json::Value {
+        json!({
+            "id": id,
+            "cap": cap,
+            "enabled": true,
+            "removableAt": "0",
+            "market": { "id": market, "name": market, "inputToken": { "symbol": "USDC" } },
+        })
+    }
+
+    #[test]
+    fn test_build_allocation_orders_by_withdraw_queue() {
+        let data: VaultWithMarkets = serde_json::from_value(json!({
+            "id": "0xvault",
+            "version": "1.1",
+            "name": "USDC Vault",
+            "symbol": "vUSDC",
+            "decimals": 18,
+            "asset": { "name": "USD Coin", "symbol": "USDC", "decimals": 6 },
+            "owner": { "id": "0xowner" },
+            "curator": null,
+            "guardian": null,
+            "timelock": "86400",
+            "fee": "100000000000000000",
+            "feeRecipient": null,
+            "feeAccrued": "0",
+            "rate": { "id": "0xrate", "rate": "5", "side": "LENDER", "type": "VARIABLE" },
+            "lastTotalAssets": "1000",
+            "totalShares": "1000",
+            "idle": "100",
+            "hasPublicAllocator": false,
+            "supplyQueue": [{ "id": "0xmm-a" }],
+            "withdrawQueue": [{ "id": "0xmm-b" }, { "id": "0xmm-a" }],
+            "markets": [
+                vault_market("0xmm-a", "0xa", "1000"),
+                vault_market("0xmm-b", "0xb", "500"),
+                vault_market("0xmm-c", "0xc", "0"),
+            ],
+        }))
+        .unwrap();
+        let positions = vec![
+            SupplyPosition {
+                balance: "600".to_string(),
+                market: EntityRef {
+                    id: "0xa".to_string(),
+                },
+            },
+            SupplyPosition {
+                balance: "300".to_string(),
+                market: EntityRef {
+                    id: "0xb".to_string(),
+                },
+            },
+        ];
+
+        let allocation = build_allocation(data, &positions);
+
+        let ids: Vec<&str> = allocation
+            .markets
+            .iter()
+            .map(|m| m.market_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["0xb", "0xa", "0xc"]);
+        assert_eq!(allocation.supply_queue, vec!["0xa"]);
+        assert_eq!(allocation.withdraw_queue, vec!["0xb", "0xa"]);
+        assert_eq!(allocation.markets[0].cap_usage, 0.6);
+        assert_eq!(allocation.markets[1].share_of_vault, 0.6);
+        assert_eq!(allocation.markets[1].supply_queue_index, Some(0));
+        assert_eq!(allocation.markets[2].supplied_assets, "0");
+        assert_eq!(allocation.markets[2].withdraw_queue_index, None);
+        assert_eq!(allocation.idle_share, 0.1);
+    }
+}
@@ -0,0 +1,22 @@
+use log::warn;
+
+/// Parse a BigInt or BigDecimal string returned by a subgraph into an `f64`.
+/// Values that cannot be parsed are logged and treated as zero.
+pub(crate) fn to_f64(value: &str) -> f64 {
+    match value.parse::<f64>() {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            warn!("Failed to parse numeric value '{}': {}", value, e);
+            0.0
+        }
+    }
+}
+
+/// Divide `numerator` by `denominator`, returning zero when the denominator is zero
+pub(crate) fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator == 0.0 {
+        0.0
+    } else {
+        numerator / denominator
+    }
+}